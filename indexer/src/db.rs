@@ -0,0 +1,162 @@
+// inside db.rs
+use bpf_program_template::state::Escrow;
+use rusqlite::{params, Connection, OptionalExtension};
+use solana_sdk::{clock::Slot, pubkey::Pubkey};
+
+use crate::{decode::mode_name, error::IndexerError};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS escrows (
+    address TEXT PRIMARY KEY,
+    status TEXT,
+    mode TEXT,
+    payer TEXT,
+    payee TEXT,
+    authority TEXT,
+    vault TEXT,
+    fee_taker TEXT,
+    rent_payer TEXT,
+    reference BLOB,
+    referrer TEXT,
+    referrer_fee_bps INTEGER,
+    max_fee INTEGER,
+    authority_program TEXT,
+    amount INTEGER,
+    fee INTEGER,
+    accepted_at INTEGER,
+    first_signature TEXT NOT NULL,
+    last_signature TEXT NOT NULL,
+    updated_slot INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS escrow_events (
+    signature TEXT NOT NULL,
+    instruction_index INTEGER NOT NULL,
+    escrow TEXT NOT NULL,
+    instruction TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    PRIMARY KEY (signature, instruction_index, escrow)
+);
+CREATE INDEX IF NOT EXISTS escrow_events_escrow ON escrow_events (escrow);
+CREATE TABLE IF NOT EXISTS cursor (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    signature TEXT NOT NULL
+);
+";
+
+/// One escrow instruction found in a transaction
+pub struct EscrowEvent<'a> {
+    pub signature: &'a str,
+    pub instruction_index: usize,
+    pub escrow: Pubkey,
+    pub instruction: &'static str,
+    pub slot: Slot,
+    pub block_time: Option<i64>,
+}
+
+/// The SQLite database the escrows and their history are kept in
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    pub fn open(path: &str) -> Result<Self, IndexerError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Database { connection })
+    }
+
+    /// The last indexed signature, transactions up to it are not fetched again
+    pub fn cursor(&self) -> Result<Option<String>, IndexerError> {
+        Ok(self
+            .connection
+            .query_row("SELECT signature FROM cursor WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    pub fn set_cursor(&self, signature: &str) -> Result<(), IndexerError> {
+        self.connection.execute(
+            "INSERT INTO cursor (id, signature) VALUES (0, ?1)
+             ON CONFLICT (id) DO UPDATE SET signature = excluded.signature",
+            params![signature],
+        )?;
+        Ok(())
+    }
+
+    /// Records the event in the history of the escrow it names
+    pub fn record_event(&self, event: &EscrowEvent) -> Result<(), IndexerError> {
+        let escrow = event.escrow.to_string();
+        self.connection.execute(
+            "INSERT OR IGNORE INTO escrow_events
+             (signature, instruction_index, escrow, instruction, slot, block_time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                event.signature,
+                event.instruction_index,
+                escrow,
+                event.instruction,
+                event.slot,
+                event.block_time,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records the escrow the event touched, whose state is refreshed later
+    pub fn touch_escrow(&self, event: &EscrowEvent) -> Result<(), IndexerError> {
+        self.connection.execute(
+            "INSERT INTO escrows (address, first_signature, last_signature, updated_slot)
+             VALUES (?1, ?2, ?2, ?3)
+             ON CONFLICT (address) DO UPDATE SET
+                last_signature = excluded.last_signature,
+                updated_slot = excluded.updated_slot",
+            params![event.escrow.to_string(), event.signature, event.slot],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the current on-chain state of an escrow
+    pub fn update_escrow(&self, address: &Pubkey, escrow: &Escrow) -> Result<(), IndexerError> {
+        self.connection.execute(
+            "UPDATE escrows SET
+                status = ?2, mode = ?3, payer = ?4, payee = ?5, authority = ?6, vault = ?7,
+                fee_taker = ?8, rent_payer = ?9, reference = ?10, amount = ?11, fee = ?12,
+                accepted_at = ?13, referrer = ?14, referrer_fee_bps = ?15,
+                max_fee = ?16, authority_program = ?17
+             WHERE address = ?1",
+            params![
+                address.to_string(),
+                format!("{:?}", escrow.status),
+                mode_name(&escrow.mode),
+                escrow.payer_pubkey.to_string(),
+                escrow.payee_pubkey.to_string(),
+                escrow.authority_pubkey.to_string(),
+                escrow.payer_temp_token_account_pubkey.to_string(),
+                escrow.fee_taker_pubkey.to_string(),
+                escrow.rent_payer_pubkey.to_string(),
+                &escrow.reference[..],
+                escrow.amount,
+                escrow.fee,
+                escrow.accepted_at,
+                (escrow.referrer_pubkey != Pubkey::default())
+                    .then(|| escrow.referrer_pubkey.to_string()),
+                escrow.referrer_fee_bps,
+                escrow.max_fee,
+                escrow.authority_program().map(Pubkey::to_string),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Marks an escrow whose account no longer holds escrow data as closed,
+    /// keeping the last state that was indexed
+    pub fn close_escrow(&self, address: &Pubkey) -> Result<(), IndexerError> {
+        self.connection.execute(
+            "UPDATE escrows SET status = 'Closed' WHERE address = ?1",
+            params![address.to_string()],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+// inside decode.rs
+use bpf_program_template::{instruction::EscrowInstruction, state::EscrowMode};
+use solana_sdk::pubkey::Pubkey;
+
+/// Position of the escrow account in the accounts of every single escrow instruction,
+/// see the "Accounts expected" lists of `EscrowInstruction`. `None` for the config
+/// instructions, which touch no escrow
+fn escrow_account_index(instruction: &EscrowInstruction) -> Option<usize> {
+    Some(match instruction {
+        EscrowInstruction::Quote { .. } => 0,
+        EscrowInstruction::Close | EscrowInstruction::UpdateAuthority { .. } => 1,
+        EscrowInstruction::Accept
+        | EscrowInstruction::Cancel
+        | EscrowInstruction::Attest { .. }
+        | EscrowInstruction::Refund => 2,
+        EscrowInstruction::InitEscrow { .. }
+        | EscrowInstruction::SettleByCondition
+        | EscrowInstruction::Deposit { .. } => 3,
+        EscrowInstruction::Settle { .. }
+        | EscrowInstruction::Crank
+        | EscrowInstruction::Withdraw
+        | EscrowInstruction::Revoke
+        | EscrowInstruction::Claim { .. } => 4,
+        EscrowInstruction::Exchange { .. } => 6,
+        EscrowInstruction::SettleMany { .. } => 5,
+        EscrowInstruction::InitConfig { .. }
+        | EscrowInstruction::ProposeConfigChange { .. }
+        | EscrowInstruction::ExecuteConfigChange
+        | EscrowInstruction::CancelConfigChange => return None,
+    })
+}
+
+/// The escrow accounts an instruction acts on, given the accounts it was invoked with
+pub fn escrow_accounts(instruction: &EscrowInstruction, accounts: &[Pubkey]) -> Vec<Pubkey> {
+    let first = match escrow_account_index(instruction) {
+        Some(first) => first,
+        None => return vec![],
+    };
+    match instruction {
+        // escrow, temp token account and payee triples, followed by the referrer's account with `with_referrers`
+        EscrowInstruction::SettleMany { with_referrers, .. } => accounts
+            .get(first..)
+            .unwrap_or_default()
+            .iter()
+            .step_by(if *with_referrers { 4 } else { 3 })
+            .copied()
+            .collect(),
+        _ => accounts.get(first).copied().into_iter().collect(),
+    }
+}
+
+/// Whether the instruction loads the escrow it is given. `Attest` only records the address in
+/// the attestation, unchecked, so it may be any key rather than an escrow
+pub fn loads_escrow(instruction: &EscrowInstruction) -> bool {
+    !matches!(instruction, EscrowInstruction::Attest { .. })
+}
+
+pub fn instruction_name(instruction: &EscrowInstruction) -> &'static str {
+    match instruction {
+        EscrowInstruction::InitEscrow { .. } => "InitEscrow",
+        EscrowInstruction::Settle { .. } => "Settle",
+        EscrowInstruction::Close => "Close",
+        EscrowInstruction::UpdateAuthority { .. } => "UpdateAuthority",
+        EscrowInstruction::Accept => "Accept",
+        EscrowInstruction::Cancel => "Cancel",
+        EscrowInstruction::Crank => "Crank",
+        EscrowInstruction::Withdraw => "Withdraw",
+        EscrowInstruction::Revoke => "Revoke",
+        EscrowInstruction::Attest { .. } => "Attest",
+        EscrowInstruction::SettleByCondition => "SettleByCondition",
+        EscrowInstruction::Claim { .. } => "Claim",
+        EscrowInstruction::Refund => "Refund",
+        EscrowInstruction::Exchange { .. } => "Exchange",
+        EscrowInstruction::SettleMany { .. } => "SettleMany",
+        EscrowInstruction::Deposit { .. } => "Deposit",
+        EscrowInstruction::Quote { .. } => "Quote",
+        EscrowInstruction::InitConfig { .. } => "InitConfig",
+        EscrowInstruction::ProposeConfigChange { .. } => "ProposeConfigChange",
+        EscrowInstruction::ExecuteConfigChange => "ExecuteConfigChange",
+        EscrowInstruction::CancelConfigChange => "CancelConfigChange",
+    }
+}
+
+pub fn mode_name(mode: &EscrowMode) -> &'static str {
+    match mode {
+        EscrowMode::Standard => "Standard",
+        EscrowMode::Recurring { .. } => "Recurring",
+        EscrowMode::Vesting { .. } => "Vesting",
+        EscrowMode::Conditional { .. } => "Conditional",
+        EscrowMode::HashLock { .. } => "HashLock",
+        EscrowMode::Swap { .. } => "Swap",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bpf_program_template::{find_escrow_address, governance::ConfigParams, id, instruction};
+    use solana_sdk::instruction::Instruction;
+
+    /// The escrows `escrow_accounts` extracts from a built instruction
+    fn extracted(ix: Instruction) -> Vec<Pubkey> {
+        let accounts: Vec<_> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        escrow_accounts(&EscrowInstruction::unpack(&ix.data).unwrap(), &accounts)
+    }
+
+    fn key() -> Pubkey {
+        Pubkey::new_unique()
+    }
+
+    fn config_params() -> ConfigParams {
+        ConfigParams {
+            admin: key(),
+            fee_taker: key(),
+            max_fee_bps: 100,
+            paused: false,
+            delay: 0,
+            allowed_mints: vec![],
+        }
+    }
+
+    #[test]
+    fn init_escrow() {
+        let (payer, authority, reference) = (key(), key(), [1; 32]);
+        let ix = instruction::init_escrow(
+            &id(),
+            &payer,
+            &key(),
+            &authority,
+            &key(),
+            &key(),
+            Some(&key()),
+            1,
+            reference,
+            0,
+            0,
+            EscrowMode::Standard,
+            None,
+        );
+        let (escrow, _) = find_escrow_address(&payer, &authority, &reference, &id());
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn settle() {
+        let escrow = key();
+        let ix = instruction::settle(
+            &id(),
+            &key(),
+            &key(),
+            &key(),
+            &key(),
+            &escrow,
+            &key(),
+            Some(&key()),
+            1,
+        );
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn close() {
+        let escrow = key();
+        let ix = instruction::close(&id(), &key(), &escrow, &key());
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn update_authority() {
+        let escrow = key();
+        let ix = instruction::update_authority(&id(), &key(), &escrow, &key(), true);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn accept() {
+        let escrow = key();
+        let ix = instruction::accept(&id(), &key(), &key(), &escrow);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn cancel() {
+        let escrow = key();
+        let ix = instruction::cancel(&id(), &key(), &key(), &escrow, &key());
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn crank() {
+        let escrow = key();
+        let ix = instruction::crank(&id(), &key(), &key(), &key(), &key(), &escrow);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn withdraw() {
+        let escrow = key();
+        let ix = instruction::withdraw(&id(), &key(), &key(), &key(), &key(), &escrow);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn revoke() {
+        let escrow = key();
+        let ix = instruction::revoke(&id(), &key(), &key(), &key(), &key(), &escrow);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn attest() {
+        let escrow = key();
+        let ix = instruction::attest(&id(), &key(), &key(), &escrow, [2; 32]);
+        assert!(!loads_escrow(&EscrowInstruction::unpack(&ix.data).unwrap()));
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn settle_by_condition() {
+        let escrow = key();
+        let ix = instruction::settle_by_condition(&id(), &key(), &key(), &key(), &escrow, &key());
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn claim() {
+        let escrow = key();
+        let ix = instruction::claim(&id(), &key(), &key(), &key(), &key(), &escrow, [3; 32]);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn refund() {
+        let escrow = key();
+        let ix = instruction::refund(&id(), &key(), &key(), &escrow);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn exchange() {
+        let escrow = key();
+        let ix = instruction::exchange(
+            &id(),
+            &key(),
+            &key(),
+            &key(),
+            &key(),
+            &key(),
+            &key(),
+            &escrow,
+            1,
+        );
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn settle_many() {
+        let (first, second) = (key(), key());
+        let ix = instruction::settle_many(
+            &id(),
+            &key(),
+            &key(),
+            &key(),
+            &[(first, key(), key(), None), (second, key(), key(), None)],
+            10,
+        );
+        assert_eq!(extracted(ix), vec![first, second]);
+    }
+
+    #[test]
+    fn settle_many_with_referrers() {
+        let (first, second, third) = (key(), key(), key());
+        let ix = instruction::settle_many(
+            &id(),
+            &key(),
+            &key(),
+            &key(),
+            &[
+                (first, key(), key(), None),
+                (second, key(), key(), Some(key())),
+                (third, key(), key(), None),
+            ],
+            10,
+        );
+        assert_eq!(extracted(ix), vec![first, second, third]);
+    }
+
+    #[test]
+    fn deposit() {
+        let escrow = key();
+        let ix = instruction::deposit(&id(), &key(), &key(), &key(), &escrow, 1);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn quote() {
+        let escrow = key();
+        let ix = instruction::quote(&id(), &escrow, &key(), 1);
+        assert_eq!(extracted(ix), vec![escrow]);
+    }
+
+    #[test]
+    fn config_instructions() {
+        let admin = key();
+        for ix in [
+            instruction::init_config(&id(), &admin, config_params()),
+            instruction::propose_config_change(&id(), &admin, config_params()),
+            instruction::execute_config_change(&id()),
+            instruction::cancel_config_change(&id(), &admin),
+        ] {
+            assert_eq!(extracted(ix), vec![]);
+        }
+    }
+}
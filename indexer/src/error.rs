@@ -0,0 +1,23 @@
+// inside error.rs
+use solana_client::client_error::ClientError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IndexerError {
+    #[error("RPC request failed: {0}")]
+    Rpc(Box<ClientError>),
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Invalid public key: {0}")]
+    InvalidPubkey(#[from] solana_sdk::pubkey::ParsePubkeyError),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(#[from] solana_sdk::signature::ParseSignatureError),
+    #[error("Transaction {0} could not be decoded")]
+    UndecodableTransaction(String),
+}
+
+impl From<ClientError> for IndexerError {
+    fn from(e: ClientError) -> Self {
+        IndexerError::Rpc(Box::new(e))
+    }
+}
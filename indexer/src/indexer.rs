@@ -0,0 +1,185 @@
+// inside indexer.rs
+use std::{collections::BTreeSet, str::FromStr};
+
+use bpf_program_template::{instruction::EscrowInstruction, state::Escrow};
+use solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, program_pack::Pack, pubkey::Pubkey, signature::Signature,
+};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta, UiInstruction,
+    UiTransactionEncoding,
+};
+
+use crate::{
+    db::{Database, EscrowEvent},
+    decode::{escrow_accounts, instruction_name, loads_escrow},
+    error::IndexerError,
+};
+
+/// Largest page of signatures the RPC returns at once
+const SIGNATURES_PAGE: usize = 1000;
+
+/// Follows the transactions of the escrow program and keeps the database up to date
+pub struct Indexer {
+    rpc: RpcClient,
+    program_id: Pubkey,
+    db: Database,
+}
+
+impl Indexer {
+    pub fn new(rpc_url: String, program_id: Pubkey, db: Database) -> Self {
+        Indexer {
+            rpc: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed()),
+            program_id,
+            db,
+        }
+    }
+
+    /// Indexes every program transaction since the last poll, oldest first,
+    /// returning how many were processed
+    pub fn poll(&self) -> Result<usize, IndexerError> {
+        let until = self
+            .db
+            .cursor()?
+            .map(|signature| Signature::from_str(&signature))
+            .transpose()?;
+
+        let mut signatures = vec![];
+        let mut before = None;
+        loop {
+            let page = self.rpc.get_signatures_for_address_with_config(
+                &self.program_id,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until,
+                    limit: Some(SIGNATURES_PAGE),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )?;
+            let full_page = page.len() == SIGNATURES_PAGE;
+            before = page
+                .last()
+                .map(|status| Signature::from_str(&status.signature))
+                .transpose()?;
+            signatures.extend(page);
+            if !full_page {
+                break;
+            }
+        }
+
+        for status in signatures.iter().rev() {
+            if status.err.is_none() {
+                let signature = Signature::from_str(&status.signature)?;
+                // refreshed before the cursor moves past the transaction, so an error
+                // further on cannot leave its escrows behind
+                let touched: BTreeSet<_> =
+                    self.index_transaction(&signature)?.into_iter().collect();
+                for escrow in &touched {
+                    self.refresh_escrow(escrow)?;
+                }
+            }
+            self.db.set_cursor(&status.signature)?;
+        }
+        Ok(signatures.len())
+    }
+
+    /// Records the escrow instructions of a transaction, including the ones invoked
+    /// by other programs, returning the escrows they touched
+    fn index_transaction(&self, signature: &Signature) -> Result<Vec<Pubkey>, IndexerError> {
+        let EncodedConfirmedTransactionWithStatusMeta {
+            slot,
+            transaction,
+            block_time,
+        } = self.rpc.get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )?;
+        let undecodable = || IndexerError::UndecodableTransaction(signature.to_string());
+        let meta = transaction.meta.ok_or_else(undecodable)?;
+        let message = transaction
+            .transaction
+            .decode()
+            .ok_or_else(undecodable)?
+            .message;
+
+        let mut account_keys = message.static_account_keys().to_vec();
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            for address in loaded.writable.iter().chain(&loaded.readonly) {
+                account_keys.push(Pubkey::from_str(address)?);
+            }
+        }
+
+        let mut instructions: Vec<(u8, Vec<u8>, Vec<u8>)> = message
+            .instructions()
+            .iter()
+            .map(|ix| (ix.program_id_index, ix.accounts.clone(), ix.data.clone()))
+            .collect();
+        if let OptionSerializer::Some(inner) = &meta.inner_instructions {
+            for ix in inner.iter().flat_map(|inner| &inner.instructions) {
+                if let UiInstruction::Compiled(ix) = ix {
+                    let data = bs58::decode(&ix.data)
+                        .into_vec()
+                        .map_err(|_| undecodable())?;
+                    instructions.push((ix.program_id_index, ix.accounts.clone(), data));
+                }
+            }
+        }
+
+        let signature = signature.to_string();
+        let mut touched = vec![];
+        for (instruction_index, (program_id_index, accounts, data)) in
+            instructions.iter().enumerate()
+        {
+            if account_keys.get(*program_id_index as usize) != Some(&self.program_id) {
+                continue;
+            }
+            let instruction = match EscrowInstruction::unpack(data) {
+                Ok(instruction) => instruction,
+                Err(_) => continue,
+            };
+            let accounts: Vec<Pubkey> = accounts
+                .iter()
+                .filter_map(|index| account_keys.get(*index as usize).copied())
+                .collect();
+
+            for escrow in escrow_accounts(&instruction, &accounts) {
+                let event = EscrowEvent {
+                    signature: &signature,
+                    instruction_index,
+                    escrow,
+                    instruction: instruction_name(&instruction),
+                    slot,
+                    block_time,
+                };
+                self.db.record_event(&event)?;
+                if loads_escrow(&instruction) {
+                    self.db.touch_escrow(&event)?;
+                    touched.push(escrow);
+                }
+            }
+        }
+        Ok(touched)
+    }
+
+    /// Reads the current state of an escrow account into the database
+    fn refresh_escrow(&self, address: &Pubkey) -> Result<(), IndexerError> {
+        let account = self
+            .rpc
+            .get_account_with_commitment(address, CommitmentConfig::confirmed())?
+            .value
+            .filter(|account| account.owner == self.program_id);
+
+        match account.map(|account| Escrow::unpack(&account.data)) {
+            Some(Ok(escrow)) => self.db.update_escrow(address, &escrow),
+            _ => self.db.close_escrow(address),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Indexes the escrow program's transactions into a SQLite database.
+//!
+//! Usage: `escrow-indexer [RPC_URL] [DATABASE] [PROGRAM_ID]`, polling every `POLL_INTERVAL`
+//! seconds (5 by default). Defaults to a local test validator, `escrows.db` and the program's
+//! declared id.
+
+mod db;
+mod decode;
+mod error;
+mod indexer;
+
+use std::{env, str::FromStr, thread, time::Duration};
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{db::Database, error::IndexerError, indexer::Indexer};
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_DATABASE: &str = "escrows.db";
+const DEFAULT_POLL_INTERVAL: u64 = 5;
+
+fn main() -> Result<(), IndexerError> {
+    let mut args = env::args().skip(1);
+    let rpc_url = args.next().unwrap_or_else(|| DEFAULT_RPC_URL.to_string());
+    let database = args.next().unwrap_or_else(|| DEFAULT_DATABASE.to_string());
+    let program_id = args
+        .next()
+        .map(|program_id| Pubkey::from_str(&program_id))
+        .transpose()?
+        .unwrap_or_else(bpf_program_template::id);
+    let poll_interval = env::var("POLL_INTERVAL")
+        .ok()
+        .and_then(|interval| interval.parse().ok())
+        .unwrap_or(DEFAULT_POLL_INTERVAL);
+
+    let indexer = Indexer::new(rpc_url, program_id, Database::open(&database)?);
+    loop {
+        match indexer.poll() {
+            Ok(0) => {}
+            Ok(count) => println!("Indexed {} transactions", count),
+            Err(err) => eprintln!("Indexing failed: {}", err),
+        }
+        thread::sleep(Duration::from_secs(poll_interval));
+    }
+}
@@ -0,0 +1,179 @@
+import { PublicKey } from '@solana/web3.js';
+
+/**
+ * Custom error codes returned by the escrow program, numbered as `EscrowError`
+ * in program/src/error.rs. The numbers are stable, new codes are only appended.
+ */
+export enum EscrowErrorCode {
+    InvalidInstruction = 0,
+    NotRentExempt = 1,
+    ExpectedAmountMismatch = 2,
+    InvalidAuthorityId = 3,
+    AmountOverflow = 4,
+    AccountAlreadySettled = 5,
+    FeeOverflow = 6,
+    AccountNotSettled = 7,
+    EscrowAlreadyAccepted = 8,
+    EscrowNotAccepted = 9,
+    EscrowDisputed = 10,
+    EscrowAlreadyRefunded = 11,
+    EscrowAlreadyCancelled = 12,
+    EscrowExpired = 13,
+    InvalidStatusTransition = 14,
+    InvalidEscrowMode = 15,
+    InvalidReleaseSchedule = 16,
+    ReleaseNotDue = 17,
+    ConditionNotMet = 18,
+    InvalidPreimage = 19,
+    HashLockNotExpired = 20,
+    EscrowClosed = 21,
+    InvalidEscrowOwner = 22,
+    InvalidAttestationOwner = 23,
+    InvalidPda = 24,
+    VaultMismatch = 25,
+    RentPayerMismatch = 26,
+    PayeeMismatch = 27,
+    PayerMismatch = 28,
+    NewAuthorityMismatch = 29,
+    ReceiveAccountMismatch = 30,
+    EscrowAddressMismatch = 31,
+    InvalidFeeSplit = 32,
+    FeeTakerMismatch = 33,
+    ReferrerMismatch = 34,
+    FeeAboveMaximum = 35,
+    InvalidConfig = 36,
+    InvalidGovernance = 37,
+    AdminMismatch = 38,
+    EscrowsPaused = 39,
+    MintNotAllowed = 40,
+    FeeRateAboveMaximum = 41,
+    NoPendingConfigChange = 42,
+    ConfigChangeNotDue = 43,
+    InvalidConfigParams = 44,
+    InvalidUpgradeAuthority = 45,
+    AuthorityProgramMismatch = 46,
+}
+
+const ESCROW_ERROR_MESSAGES: Record<EscrowErrorCode, string> = {
+    [EscrowErrorCode.InvalidInstruction]: 'Invalid Instruction',
+    [EscrowErrorCode.NotRentExempt]: 'No rent excemption',
+    [EscrowErrorCode.ExpectedAmountMismatch]: 'Amount mismatch',
+    [EscrowErrorCode.InvalidAuthorityId]: 'Authority is invalid',
+    [EscrowErrorCode.AmountOverflow]: 'Amount overflow',
+    [EscrowErrorCode.AccountAlreadySettled]: 'Account already settled',
+    [EscrowErrorCode.FeeOverflow]: 'Fee overflow',
+    [EscrowErrorCode.AccountNotSettled]: 'Account not settled',
+    [EscrowErrorCode.EscrowAlreadyAccepted]: 'Escrow already accepted',
+    [EscrowErrorCode.EscrowNotAccepted]: 'Escrow not accepted',
+    [EscrowErrorCode.EscrowDisputed]: 'Escrow disputed',
+    [EscrowErrorCode.EscrowAlreadyRefunded]: 'Escrow already refunded',
+    [EscrowErrorCode.EscrowAlreadyCancelled]: 'Escrow already cancelled',
+    [EscrowErrorCode.EscrowExpired]: 'Escrow expired',
+    [EscrowErrorCode.InvalidStatusTransition]: 'Invalid escrow status transition',
+    [EscrowErrorCode.InvalidEscrowMode]: 'Not supported by the escrow mode',
+    [EscrowErrorCode.InvalidReleaseSchedule]: 'Invalid release schedule',
+    [EscrowErrorCode.ReleaseNotDue]: 'Release not due yet',
+    [EscrowErrorCode.ConditionNotMet]: 'Escrow condition not met',
+    [EscrowErrorCode.InvalidPreimage]: 'Preimage does not match the hash lock',
+    [EscrowErrorCode.HashLockNotExpired]: 'Hash lock not expired',
+    [EscrowErrorCode.EscrowClosed]: 'Escrow account closed',
+    [EscrowErrorCode.InvalidEscrowOwner]: 'Escrow account not owned by the program',
+    [EscrowErrorCode.InvalidAttestationOwner]: 'Attestation account not owned by the program',
+    [EscrowErrorCode.InvalidPda]: "PDA account does not match the escrow's",
+    [EscrowErrorCode.VaultMismatch]: 'Temp token account does not belong to the escrow',
+    [EscrowErrorCode.RentPayerMismatch]: "Account is not the escrow's rent payer",
+    [EscrowErrorCode.PayeeMismatch]: "Account does not belong to the escrow's payee",
+    [EscrowErrorCode.PayerMismatch]: "Account does not belong to the escrow's payer",
+    [EscrowErrorCode.NewAuthorityMismatch]: 'New authority account does not match the instruction',
+    [EscrowErrorCode.ReceiveAccountMismatch]: "Account is not the swap's receiving account",
+    [EscrowErrorCode.EscrowAddressMismatch]: 'Escrow account is not at its derived address',
+    [EscrowErrorCode.InvalidFeeSplit]: 'Referrer fee share above 100% or without a referrer',
+    [EscrowErrorCode.FeeTakerMismatch]: "Account does not belong to the escrow's fee taker",
+    [EscrowErrorCode.ReferrerMismatch]: "Account does not belong to the escrow's referrer",
+    [EscrowErrorCode.FeeAboveMaximum]: 'Fee above the maximum the payer agreed to',
+    [EscrowErrorCode.InvalidConfig]: "Config account is not the program's",
+    [EscrowErrorCode.InvalidGovernance]: "Governance account is not the program's",
+    [EscrowErrorCode.AdminMismatch]: 'Signer is not the config admin',
+    [EscrowErrorCode.EscrowsPaused]: 'Opening escrows is paused',
+    [EscrowErrorCode.MintNotAllowed]: 'Mint not allowed by the config',
+    [EscrowErrorCode.FeeRateAboveMaximum]: 'Max fee above the configured fee rate',
+    [EscrowErrorCode.NoPendingConfigChange]: 'No config change pending',
+    [EscrowErrorCode.ConfigChangeNotDue]: 'Config change delay not elapsed',
+    [EscrowErrorCode.InvalidConfigParams]: 'Invalid config params',
+    [EscrowErrorCode.InvalidUpgradeAuthority]: "Signer is not the program's upgrade authority",
+    [EscrowErrorCode.AuthorityProgramMismatch]: 'Authority is not a PDA of the given program',
+};
+
+export interface EscrowError {
+    code: EscrowErrorCode;
+    name: string;
+    message: string;
+    /** Index of the failing instruction in the transaction, when known */
+    instructionIndex?: number;
+}
+
+export const escrowErrorFromCode = (code: number, instructionIndex?: number): EscrowError | null => {
+    if (!(code in ESCROW_ERROR_MESSAGES)) {
+        return null;
+    }
+    return {
+        code,
+        name: EscrowErrorCode[code],
+        message: ESCROW_ERROR_MESSAGES[code as EscrowErrorCode],
+        instructionIndex,
+    };
+}
+
+const CUSTOM_ERROR_LOG = /custom program error: (0x[0-9a-fA-F]+)/;
+const PROGRAM_FAILED_LOG = /^Program (\w+) failed: /;
+
+/**
+ * The program that failed a transaction, read from its logs. A program failing in a CPI
+ * is logged before the programs invoking it, so the first failure is the one to blame.
+ */
+const failingProgram = (logs: string[]): string | null => {
+    for (const line of logs) {
+        const match = PROGRAM_FAILED_LOG.exec(line);
+        if (match) {
+            return match[1];
+        }
+    }
+    return null;
+}
+
+/**
+ * Maps a failed transaction back to the escrow error that caused it. Accepts the `err` of
+ * a transaction status (`{ InstructionError: [index, { Custom: code }] }`) as well as the
+ * error thrown by `sendRawTransaction` during preflight, whose message and logs carry the
+ * code in hex. A custom error code alone does not tell which program returned it, a token
+ * program CPI failing with its own codes, so the logs have to show the escrow program
+ * failed first: the preflight error's own logs, or the `logMessages` of the transaction
+ * for a status `err`. Returns null when the failure is not a custom program error of the
+ * escrow, or when no logs tell.
+ */
+export const decodeEscrowError = (
+    err: any,
+    escrowProgram: PublicKey,
+    logs?: string[] | null
+): EscrowError | null => {
+    const programLogs: string[] = logs ?? err?.logs ?? [];
+    if (!err || failingProgram(programLogs) !== escrowProgram.toBase58()) {
+        return null;
+    }
+    const instructionError = err.InstructionError ?? err.err?.InstructionError;
+    if (Array.isArray(instructionError)) {
+        const [index, detail] = instructionError;
+        if (detail && typeof detail.Custom === 'number') {
+            return escrowErrorFromCode(detail.Custom, index);
+        }
+        return null;
+    }
+    const lines: string[] = [err.message, ...programLogs].filter((line) => typeof line === 'string');
+    for (const line of lines) {
+        const match = CUSTOM_ERROR_LOG.exec(line);
+        if (match) {
+            return escrowErrorFromCode(parseInt(match[1], 16));
+        }
+    }
+    return null;
+}
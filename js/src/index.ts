@@ -3,6 +3,7 @@ import {
     Transaction,
     TransactionInstruction,
     SYSVAR_RENT_PUBKEY,
+    SYSVAR_CLOCK_PUBKEY,
     SystemProgram,
     Connection,
     Keypair
@@ -19,12 +20,16 @@ import {
     TransferOutput,
     InitializePaymentOutput,
     SettlePaymentOutput,
-    ClosePaymentInput
+    ClosePaymentInput,
+    AcceptPaymentInput,
+    CancelPaymentInput,
+    SettleManyPaymentsInput
 } from './types';
 import {
     transfer,
     memoInstruction,
     EscrowLayout,
+    EscrowStatus,
     Token,
     TokenAccountLayout,
     WRAPPED_SOL_MINT,
@@ -35,6 +40,9 @@ import {
     ASSOCIATED_TOKEN_PROGRAM_ID,
     createAssociatedTokenAccountIx
 } from './instructions'; //assertOwner,
+import { decodeEscrowError } from './errors';
+
+export * from './errors';
 
 export const FAILED_TO_FIND_ACCOUNT = 'Failed to find account';
 export const INVALID_ACCOUNT_OWNER = 'Invalid account owner';
@@ -42,18 +50,21 @@ export const INVALID_AUTHORITY = 'Invalid authority';
 export const INVALID_SIGNATURE = 'Invalid signature';
 export const AMOUNT_MISMATCH = 'Amount mismatch';
 export const FEE_MISMATCH = 'Fee mismatch';
+export const FEE_ABOVE_MAXIMUM = 'Fee above the maximum the payer agreed to';
 export const TRANSACTION_SEND_ERROR = 'Transaction send error';
+export const INVALID_ORDER_ID = 'Order id longer than 32 bytes';
+export const MIXED_MINTS = 'Escrows settled together must hold the same mint';
 
 export interface EscrowAccount {
-    isInitialized: boolean,
-    isSettled: boolean,
+    status: EscrowStatus,
     payerPubkey: PublicKey,
     payeePubkey: PublicKey,
     payerTempTokenAccountPubkey: PublicKey,
     authorityPubkey: PublicKey,
     feeTakerPubkey: PublicKey,
     amount: BN,
-    fee: BN
+    fee: BN,
+    acceptedAt: number
 }
 
 export class WalletServiceClient {
@@ -71,6 +82,72 @@ export class WalletServiceClient {
         this.escrowProgram = escrowProgram;
     }
 
+    acceptEscrowPayment = async (
+        input: AcceptPaymentInput
+    ): Promise<TransferOutput> => {
+        const acceptInstruction = new TransactionInstruction({
+            programId: this.escrowProgram,
+            data: Buffer.from(Uint8Array.of(4)),
+            keys: [
+                { pubkey: new PublicKey(input.walletAddress), isSigner: true, isWritable: false },
+                { pubkey: this.authority.publicKey, isSigner: true, isWritable: false },
+                { pubkey: new PublicKey(input.escrowAddress), isSigner: false, isWritable: true },
+                { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+            ]
+        })
+        const transaction = new Transaction().add(acceptInstruction);
+        if (input.memo) {
+            transaction.add(memoInstruction(input.memo, this.authority.publicKey))
+        }
+        transaction.recentBlockhash = input.recentBlockhash;
+        transaction.feePayer = this.feePayer.publicKey;
+        transaction.partialSign(this.feePayer, this.authority);
+        const signatures = transaction.signatures.filter(signature => !!signature.signature)
+            .map(signature => signature.signature!.toString('base64'));
+        return {
+            signatures,
+            message: transaction.serializeMessage().toString('base64'),
+        }
+    }
+
+    cancelEscrowPayment = async (
+        input: CancelPaymentInput
+    ): Promise<TransferOutput> => {
+        const walletAddress = new PublicKey(input.walletAddress);
+        const escrowAddress = new PublicKey(input.escrowAddress);
+        const info = await this.connection.getAccountInfo(escrowAddress);
+        if (!info) {
+            throw new Error(FAILED_TO_FIND_ACCOUNT);
+        }
+        if (!info.owner.equals(this.escrowProgram)) {
+            throw new Error(INVALID_ACCOUNT_OWNER);
+        }
+        const accountInfo = ESCROW_ACCOUNT_DATA_LAYOUT.decode(info.data) as EscrowLayout;
+        const PDA = await PublicKey.findProgramAddress([Buffer.from("escrow")], this.escrowProgram);
+        const cancelInstruction = new TransactionInstruction({
+            programId: this.escrowProgram,
+            data: Buffer.from(Uint8Array.of(5)),
+            keys: [
+                { pubkey: walletAddress, isSigner: true, isWritable: false },
+                { pubkey: accountInfo.payerTempTokenAccountPubkey, isSigner: false, isWritable: true },
+                { pubkey: escrowAddress, isSigner: false, isWritable: true },
+                { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+                { pubkey: PDA[0], isSigner: false, isWritable: false },
+                { pubkey: accountInfo.rentPayerPubkey, isSigner: false, isWritable: true }
+            ]
+        })
+        const transaction = new Transaction().add(cancelInstruction);
+        transaction.recentBlockhash = input.recentBlockhash;
+        transaction.feePayer = this.feePayer.publicKey;
+        transaction.partialSign(this.feePayer);
+        const signatures = transaction.signatures.filter(signature => !!signature.signature)
+            .map(signature => signature.signature!.toString('base64'));
+        return {
+            signatures,
+            message: transaction.serializeMessage().toString('base64'),
+        }
+    }
+
     closeEscrowPayment = async (
         input: ClosePaymentInput
     ): Promise<string> => {
@@ -316,15 +393,8 @@ export class WalletServiceClient {
         } else {
             transferXTokensToTempAccIx = transfer(tokenAccountAddress, tempTokenAccount.publicKey, input.amount, walletAddress);
         }
-        const escrowAccount = new Keypair();
-
-        const createEscrowAccountIx = SystemProgram.createAccount({
-            space: ESCROW_ACCOUNT_DATA_LAYOUT.span,
-            lamports: await this.connection.getMinimumBalanceForRentExemption(ESCROW_ACCOUNT_DATA_LAYOUT.span, 'singleGossip'),
-            fromPubkey: this.feePayer.publicKey,
-            newAccountPubkey: escrowAccount.publicKey,
-            programId: this.escrowProgram
-        });
+        const reference = orderReference(input.orderId);
+        const escrowAddress = await this.findEscrowAddress(walletAddress, input.orderId);
 
         const initEscrowIx = new TransactionInstruction({
             programId: this.escrowProgram,
@@ -332,33 +402,66 @@ export class WalletServiceClient {
                 { pubkey: walletAddress, isSigner: true, isWritable: false },
                 { pubkey: tempTokenAccount.publicKey, isSigner: false, isWritable: true },
                 { pubkey: this.authority.publicKey, isSigner: true, isWritable: false },
-                { pubkey: escrowAccount.publicKey, isSigner: false, isWritable: true },
+                { pubkey: escrowAddress, isSigner: false, isWritable: true },
                 { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
                 { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+                { pubkey: this.feePayer.publicKey, isSigner: true, isWritable: true },
+                { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+                { pubkey: this.feeTaker, isSigner: false, isWritable: false },
+                { pubkey: await this.findConfigAddress(), isSigner: false, isWritable: false },
             ],
-            data: Buffer.from(Uint8Array.of(0, ...new BN(input.amount).toArray("le", 8)))
+            data: Buffer.from(Uint8Array.of(
+                0,
+                ...new BN(input.amount).toArray("le", 8),
+                ...reference,
+                ...new BN(input.referrer ? input.referrerFeeBps || 0 : 0).toArray("le", 2),
+                ...new BN(input.maxFee).toArray("le", 8),
+            ))
         })
+        if (input.referrer) {
+            initEscrowIx.keys.push({ pubkey: new PublicKey(input.referrer), isSigner: false, isWritable: false });
+        }
         const transaction = new Transaction()
             .add(createTempTokenAccountIx);
         if (tokenMintAddress.equals(WRAPPED_SOL_MINT)) {
-            transaction.add(transferXTokensToTempAccIx, initTempAccountIx, createEscrowAccountIx, initEscrowIx,)
+            transaction.add(transferXTokensToTempAccIx, initTempAccountIx, initEscrowIx,)
         } else {
-            transaction.add(initTempAccountIx, transferXTokensToTempAccIx, createEscrowAccountIx, initEscrowIx,)
+            transaction.add(initTempAccountIx, transferXTokensToTempAccIx, initEscrowIx,)
         }
         if (input.memo) {
             transaction.add(memoInstruction(input.memo, this.authority.publicKey))
         }
         transaction.recentBlockhash = (await this.connection.getRecentBlockhash()).blockhash;
         transaction.feePayer = this.feePayer.publicKey;
-        transaction.partialSign(this.feePayer, escrowAccount, tempTokenAccount,);
+        transaction.partialSign(this.feePayer, tempTokenAccount,);
         const signatures = transaction.signatures.map(sig => ({ signature: sig.signature && sig.signature.toString('base64'), pubKey: sig.publicKey.toBase58() }));
         return {
             message: transaction.serializeMessage().toString('base64'),
             signatures: signatures,
-            escrowAddress: escrowAccount.publicKey.toBase58()
+            escrowAddress: escrowAddress.toBase58()
         }
     }
 
+    // The escrow address is derived from the authority it was created under, which UpdateAuthority
+    // does not change: pass that original authority for escrows whose authority was rotated since
+    findEscrowAddress = async (
+        walletAddress: PublicKey,
+        orderId: string,
+        creationAuthority: PublicKey = this.authority.publicKey
+    ): Promise<PublicKey> => {
+        const [escrowAddress] = await PublicKey.findProgramAddress(
+            [Buffer.from("escrow"), walletAddress.toBuffer(), creationAuthority.toBuffer(), orderReference(orderId)],
+            this.escrowProgram,
+        );
+        return escrowAddress;
+    }
+
+    // The program's config account, holding the fee taker and fee policy escrows are opened under
+    findConfigAddress = async (): Promise<PublicKey> => {
+        const [configAddress] = await PublicKey.findProgramAddress([Buffer.from("config")], this.escrowProgram);
+        return configAddress;
+    }
+
     sendEscrowPayment = async (payload: string): Promise<string> => {
         const buffer = Buffer.from(payload, 'base64');
         const txIx = Transaction.from(buffer)
@@ -385,15 +488,16 @@ export class WalletServiceClient {
         const accountInfo = ESCROW_ACCOUNT_DATA_LAYOUT.decode(info.data) as EscrowLayout;
         const escrowState = {
             escrowAddress,
-            isInitialized: !!accountInfo.isInitialized,
-            isSettled: !!accountInfo.isSettled,
+            status: accountInfo.status as EscrowStatus,
             payerPubkey: accountInfo.payerPubkey,
             payeePubkey: accountInfo.payeePubkey,
             payerTempTokenAccountPubkey: accountInfo.payerTempTokenAccountPubkey,
             authorityPubkey: accountInfo.authorityPubkey,
             feeTakerPubkey: accountInfo.feeTakerPubkey,
             expectedAmount: new BN(accountInfo.amount, 10, "le"),
-            fee: new BN(accountInfo.fee, 10, "le")
+            fee: new BN(accountInfo.fee, 10, "le"),
+            maxFee: new BN(accountInfo.maxFee, 10, "le"),
+            acceptedAt: accountInfo.acceptedAt
         };
         const expectedAmount = new BN(input.amount);
         const fee = new BN(input.fee || 0);
@@ -404,13 +508,21 @@ export class WalletServiceClient {
         if (!this.authority.publicKey.equals(escrowState.authorityPubkey)) {
             throw new Error(INVALID_AUTHORITY);
         }
+        if (fee.gt(escrowState.maxFee)) {
+            throw new Error(FEE_ABOVE_MAXIMUM);
+        }
         const token = await this.getTokenAccountInfo(escrowState.payerTempTokenAccountPubkey);
+        const hasReferrer = !accountInfo.referrerPubkey.equals(new PublicKey(0));
         let takerAccount = walletAddress;
-        let feeTakerAccount = this.feeTaker;
+        let feeTakerAccount = escrowState.feeTakerPubkey;
+        let referrerAccount = accountInfo.referrerPubkey;
 
         if (!token.isNative) {
             takerAccount = (await this.getOrCreateAssociatedAccountInfo(walletAddress, token.mint)).address;
-            feeTakerAccount = (await this.getOrCreateAssociatedAccountInfo(this.feeTaker, token.mint)).address;
+            feeTakerAccount = (await this.getOrCreateAssociatedAccountInfo(escrowState.feeTakerPubkey, token.mint)).address;
+            if (hasReferrer) {
+                referrerAccount = (await this.getOrCreateAssociatedAccountInfo(accountInfo.referrerPubkey, token.mint)).address;
+            }
         }
         const PDA = await PublicKey.findProgramAddress([Buffer.from("escrow")], this.escrowProgram);
         const exchangeInstruction = new TransactionInstruction({
@@ -427,6 +539,9 @@ export class WalletServiceClient {
                 { pubkey: PDA[0], isSigner: false, isWritable: false }
             ]
         })
+        if (hasReferrer) {
+            exchangeInstruction.keys.push({ pubkey: referrerAccount, isSigner: false, isWritable: true });
+        }
         const transaction = new Transaction().add(exchangeInstruction);
         if (input.memo) {
             transaction.add(memoInstruction(input.memo, this.authority.publicKey))
@@ -440,10 +555,92 @@ export class WalletServiceClient {
         } catch (error) {
             const newError: any = new Error(TRANSACTION_SEND_ERROR);
             newError.destinationWalletAddress = takerAccount
+            newError.escrowError = decodeEscrowError(error, this.escrowProgram)
             throw newError;
         }
     }
 
+    settleEscrowPayments = async (
+        input: SettleManyPaymentsInput
+    ): Promise<string> => {
+        const PDA = await PublicKey.findProgramAddress([Buffer.from("escrow")], this.escrowProgram);
+        const keys = [
+            { pubkey: this.authority.publicKey, isSigner: true, isWritable: false },
+            { pubkey: this.feeTaker, isSigner: false, isWritable: true },
+            { pubkey: this.feePayer.publicKey, isSigner: false, isWritable: true },
+            { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: PDA[0], isSigner: false, isWritable: false }
+        ];
+        const settlements: { escrow: PublicKey, tempTokenAccount: PublicKey, takerAccount: PublicKey, referrerAccount?: PublicKey }[] = [];
+        // the whole batch pays its fees into a single fee taker account
+        let mint: PublicKey | undefined;
+        for (const payment of input.payments) {
+            const walletAddress = new PublicKey(payment.walletAddress);
+            const escrowAddress = new PublicKey(payment.escrowAddress);
+            const info = await this.connection.getAccountInfo(escrowAddress);
+            if (!info) {
+                throw new Error(FAILED_TO_FIND_ACCOUNT);
+            }
+            if (!info.owner.equals(this.escrowProgram)) {
+                throw new Error(INVALID_ACCOUNT_OWNER);
+            }
+            const accountInfo = ESCROW_ACCOUNT_DATA_LAYOUT.decode(info.data) as EscrowLayout;
+            if (!this.authority.publicKey.equals(accountInfo.authorityPubkey)) {
+                throw new Error(INVALID_AUTHORITY);
+            }
+            const token = await this.getTokenAccountInfo(accountInfo.payerTempTokenAccountPubkey);
+            const firstPayment = !mint;
+            if (mint && !mint.equals(token.mint)) {
+                throw new Error(MIXED_MINTS);
+            }
+            mint = token.mint;
+            const hasReferrer = !accountInfo.referrerPubkey.equals(new PublicKey(0));
+            let takerAccount = walletAddress;
+            let referrerAccount = hasReferrer ? accountInfo.referrerPubkey : undefined;
+            if (!token.isNative) {
+                takerAccount = (await this.getOrCreateAssociatedAccountInfo(walletAddress, token.mint)).address;
+                if (firstPayment) {
+                    keys[1].pubkey = (await this.getOrCreateAssociatedAccountInfo(this.feeTaker, token.mint)).address;
+                }
+                if (hasReferrer) {
+                    referrerAccount = (await this.getOrCreateAssociatedAccountInfo(accountInfo.referrerPubkey, token.mint)).address;
+                }
+            }
+            settlements.push({
+                escrow: escrowAddress,
+                tempTokenAccount: accountInfo.payerTempTokenAccountPubkey,
+                takerAccount,
+                referrerAccount
+            });
+        }
+        // every escrow gets a referrer account once one has a referrer, ignored for the others
+        const withReferrers = settlements.some(settlement => settlement.referrerAccount);
+        for (const settlement of settlements) {
+            keys.push(
+                { pubkey: settlement.escrow, isSigner: false, isWritable: true },
+                { pubkey: settlement.tempTokenAccount, isSigner: false, isWritable: true },
+                { pubkey: settlement.takerAccount, isSigner: false, isWritable: true },
+            );
+            if (withReferrers) {
+                keys.push({ pubkey: settlement.referrerAccount || settlement.takerAccount, isSigner: false, isWritable: true });
+            }
+        }
+        const feeBasisPoints = new BN(input.feeBasisPoints || 0);
+        const settleManyInstruction = new TransactionInstruction({
+            programId: this.escrowProgram,
+            data: Buffer.from(Uint8Array.of(14, ...feeBasisPoints.toArray("le", 2), ...(withReferrers ? [1] : []))),
+            keys
+        })
+        const transaction = new Transaction().add(settleManyInstruction);
+        if (input.memo) {
+            transaction.add(memoInstruction(input.memo, this.authority.publicKey))
+        }
+        transaction.recentBlockhash = (await this.connection.getRecentBlockhash()).blockhash;
+        transaction.feePayer = this.feePayer.publicKey;
+        transaction.sign(this.feePayer, this.authority);
+        return await this.connection.sendRawTransaction(transaction.serialize(), { skipPreflight: false });
+    }
+
     signTransaction = (
         transaction: Transaction,
     ): Buffer => {
@@ -474,6 +671,16 @@ export class WalletServiceClient {
     }
 }
 
+// The 32 byte escrow reference of an order id, zero padded
+const orderReference = (orderId: string): Buffer => {
+    const reference = Buffer.alloc(32);
+    if (Buffer.byteLength(orderId) > reference.length) {
+        throw new Error(INVALID_ORDER_ID);
+    }
+    reference.write(orderId);
+    return reference;
+}
+
 const findAssociatedTokenAddress = async (
     walletAddress: PublicKey,
     tokenMintAddress: PublicKey,
@@ -84,27 +84,66 @@ export const ACCOUNT_LAYOUT = BufferLayout.struct([
 ]);
 
 export const ESCROW_ACCOUNT_DATA_LAYOUT = BufferLayout.struct([
-    BufferLayout.u8("isInitialized"),
-    BufferLayout.u8("isSettled"),
+    BufferLayout.u8("status"),
     publicKeyLayout("payerPubkey"),
     publicKeyLayout("payeePubkey"),
     publicKeyLayout("payerTempTokenAccountPubkey"),
     publicKeyLayout("authorityPubkey"),
     publicKeyLayout("feeTakerPubkey"),
     uint64('amount'),
-    uint64('fee')
+    uint64('fee'),
+    BufferLayout.ns64('acceptedAt'),
+    publicKeyLayout("rentPayerPubkey"),
+    BufferLayout.u8('bumpSeed'),
+    BufferLayout.blob(32, 'reference'),
+    publicKeyLayout("referrerPubkey"),
+    BufferLayout.u16('referrerFeeBps'),
+    uint64('maxFee'),
+    publicKeyLayout("authorityProgramId"),
+    BufferLayout.u8('mode'),
+    BufferLayout.blob(96, 'modeParams')
 ]);
 
+export enum EscrowStatus {
+    Uninitialized,
+    Pending,
+    Active,
+    Disputed,
+    Settled,
+    Refunded,
+    Cancelled,
+    Expired,
+    Closed,
+}
+
+export enum EscrowMode {
+    Standard,
+    Recurring,
+    Vesting,
+    Conditional,
+    HashLock,
+    Swap,
+}
+
 export interface EscrowLayout {
-    isInitialized: number,
-    isSettled: number,
+    status: number,
     payerPubkey: PublicKey,
     payeePubkey: PublicKey,
     payerTempTokenAccountPubkey: PublicKey,
     authorityPubkey: PublicKey,
     feeTakerPubkey: PublicKey,
     amount: Buffer,
-    fee: Buffer
+    fee: Buffer,
+    acceptedAt: number,
+    rentPayerPubkey: PublicKey,
+    bumpSeed: number,
+    reference: Buffer,
+    referrerPubkey: PublicKey,
+    referrerFeeBps: number,
+    maxFee: Buffer,
+    authorityProgramId: PublicKey,
+    mode: EscrowMode,
+    modeParams: Buffer
 }
 
 export const TokenAccountLayout = BufferLayout.struct(
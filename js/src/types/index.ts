@@ -63,7 +63,13 @@ export interface InitializePaymentInput {
     tokenAccountAddress: string;
     tokenMintAddress: string;
     amount: number;
+    orderId: string;
+    // the highest fee the payer agrees to be charged on settlement
+    maxFee: number;
     memo?: string;
+    referrer?: string;
+    // the referrer's share of the settlement fee, in basis points
+    referrerFeeBps?: number;
 }
 
 export interface SendPaymentInput {
@@ -79,6 +85,26 @@ export interface SettlePaymentInput {
     fee?: number;
 }
 
+export interface AcceptPaymentInput {
+    walletAddress: string;
+    escrowAddress: string;
+    recentBlockhash: string;
+    memo?: string;
+}
+
+export interface CancelPaymentInput {
+    walletAddress: string;
+    escrowAddress: string;
+    recentBlockhash: string;
+}
+
+export interface SettleManyPaymentsInput {
+    // escrows of a single mint, the batch is rejected with MIXED_MINTS otherwise
+    payments: { walletAddress: string, escrowAddress: string }[];
+    feeBasisPoints?: number;
+    memo?: string;
+}
+
 export interface ClosePaymentInput {
     escrowAddress: string
     memo?: string;
@@ -0,0 +1,616 @@
+// inside cpi.rs
+//! Cross program invocation helpers for programs calling the escrow program.
+//!
+//! Every function builds the instruction with the matching builder of `instruction` and invokes
+//! it with `signer_seeds`, so a PDA of the calling program can sign as payer or authority. The
+//! rent payer funds accounts through the system program and has to be a data-less system account.
+
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program::invoke_signed,
+};
+
+use crate::{
+    instruction::{self, AuthorityProgram},
+    state::EscrowMode,
+};
+
+/// Invokes `InitEscrow`
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow<'a>(
+    escrow_program: AccountInfo<'a>,
+    payer: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    rent_sysvar: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    system_program: AccountInfo<'a>,
+    fee_taker: AccountInfo<'a>,
+    config: AccountInfo<'a>,
+    referrer: Option<AccountInfo<'a>>,
+    amount: u64,
+    reference: [u8; 32],
+    referrer_fee_bps: u16,
+    max_fee: u64,
+    mode: EscrowMode,
+    authority_program: Option<AuthorityProgram>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::init_escrow(
+        escrow_program.key,
+        payer.key,
+        temp_token_account.key,
+        authority.key,
+        rent_payer.key,
+        fee_taker.key,
+        referrer.as_ref().map(|referrer| referrer.key),
+        amount,
+        reference,
+        referrer_fee_bps,
+        max_fee,
+        mode,
+        authority_program,
+    );
+    let mut account_infos = vec![
+        payer,
+        temp_token_account,
+        authority,
+        escrow,
+        rent_sysvar,
+        token_program,
+        rent_payer,
+        system_program,
+        fee_taker,
+        config,
+    ];
+    account_infos.extend(referrer);
+    account_infos.push(escrow_program);
+    invoke_signed(&ix, &account_infos, signer_seeds)
+}
+
+/// Invokes `Settle`
+#[allow(clippy::too_many_arguments)]
+pub fn settle<'a>(
+    escrow_program: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    payee_account: AccountInfo<'a>,
+    fee_taker_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    referrer_account: Option<AccountInfo<'a>>,
+    fee: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::settle(
+        escrow_program.key,
+        authority.key,
+        payee_account.key,
+        fee_taker_account.key,
+        temp_token_account.key,
+        escrow.key,
+        rent_payer.key,
+        referrer_account.as_ref().map(|referrer| referrer.key),
+        fee,
+    );
+    let mut account_infos = vec![
+        authority,
+        payee_account,
+        fee_taker_account,
+        temp_token_account,
+        escrow,
+        rent_payer,
+        token_program,
+        vault_authority,
+    ];
+    account_infos.extend(referrer_account);
+    account_infos.push(escrow_program);
+    invoke_signed(&ix, &account_infos, signer_seeds)
+}
+
+/// Invokes `Close`
+pub fn close<'a>(
+    escrow_program: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::close(
+        escrow_program.key,
+        authority.key,
+        escrow.key,
+        rent_payer.key,
+    );
+    invoke_signed(
+        &ix,
+        &[authority, escrow, rent_payer, escrow_program],
+        signer_seeds,
+    )
+}
+
+/// Invokes `UpdateAuthority`, the new authority accepting the role when given
+pub fn update_authority<'a>(
+    escrow_program: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    new_authority: AccountInfo<'a>,
+    accept: bool,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::update_authority(
+        escrow_program.key,
+        authority.key,
+        escrow.key,
+        new_authority.key,
+        accept,
+    );
+    invoke_signed(
+        &ix,
+        &[authority, escrow, new_authority, escrow_program],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Accept`
+pub fn accept<'a>(
+    escrow_program: AccountInfo<'a>,
+    payee: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::accept(escrow_program.key, payee.key, authority.key, escrow.key);
+    invoke_signed(
+        &ix,
+        &[payee, authority, escrow, clock_sysvar, escrow_program],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Cancel`
+#[allow(clippy::too_many_arguments)]
+pub fn cancel<'a>(
+    escrow_program: AccountInfo<'a>,
+    payer: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::cancel(
+        escrow_program.key,
+        payer.key,
+        temp_token_account.key,
+        escrow.key,
+        rent_payer.key,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            payer,
+            temp_token_account,
+            escrow,
+            token_program,
+            vault_authority,
+            rent_payer,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Crank`
+#[allow(clippy::too_many_arguments)]
+pub fn crank<'a>(
+    escrow_program: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    payee_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::crank(
+        escrow_program.key,
+        authority.key,
+        payee_account.key,
+        temp_token_account.key,
+        rent_payer.key,
+        escrow.key,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            authority,
+            payee_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+            token_program,
+            vault_authority,
+            clock_sysvar,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Withdraw`
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw<'a>(
+    escrow_program: AccountInfo<'a>,
+    payee: AccountInfo<'a>,
+    payee_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::withdraw(
+        escrow_program.key,
+        payee.key,
+        payee_account.key,
+        temp_token_account.key,
+        rent_payer.key,
+        escrow.key,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            payee,
+            payee_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+            token_program,
+            vault_authority,
+            clock_sysvar,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Revoke`
+#[allow(clippy::too_many_arguments)]
+pub fn revoke<'a>(
+    escrow_program: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    payer_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::revoke(
+        escrow_program.key,
+        authority.key,
+        payer_account.key,
+        temp_token_account.key,
+        rent_payer.key,
+        escrow.key,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            authority,
+            payer_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+            token_program,
+            vault_authority,
+            clock_sysvar,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Attest`
+#[allow(clippy::too_many_arguments)]
+pub fn attest<'a>(
+    escrow_program: AccountInfo<'a>,
+    attestor: AccountInfo<'a>,
+    attestation: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    rent_sysvar: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    payload: [u8; 32],
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::attest(
+        escrow_program.key,
+        attestor.key,
+        attestation.key,
+        escrow.key,
+        payload,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            attestor,
+            attestation,
+            escrow,
+            rent_sysvar,
+            clock_sysvar,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `SettleByCondition`
+#[allow(clippy::too_many_arguments)]
+pub fn settle_by_condition<'a>(
+    escrow_program: AccountInfo<'a>,
+    payee_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    attestation: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::settle_by_condition(
+        escrow_program.key,
+        payee_account.key,
+        temp_token_account.key,
+        rent_payer.key,
+        escrow.key,
+        attestation.key,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            payee_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+            attestation,
+            token_program,
+            vault_authority,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Claim`
+#[allow(clippy::too_many_arguments)]
+pub fn claim<'a>(
+    escrow_program: AccountInfo<'a>,
+    payee: AccountInfo<'a>,
+    payee_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    preimage: [u8; 32],
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::claim(
+        escrow_program.key,
+        payee.key,
+        payee_account.key,
+        temp_token_account.key,
+        rent_payer.key,
+        escrow.key,
+        preimage,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            payee,
+            payee_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+            token_program,
+            vault_authority,
+            clock_sysvar,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Refund`
+#[allow(clippy::too_many_arguments)]
+pub fn refund<'a>(
+    escrow_program: AccountInfo<'a>,
+    payer: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::refund(
+        escrow_program.key,
+        payer.key,
+        temp_token_account.key,
+        escrow.key,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            payer,
+            temp_token_account,
+            escrow,
+            token_program,
+            vault_authority,
+            clock_sysvar,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Exchange`
+#[allow(clippy::too_many_arguments)]
+pub fn exchange<'a>(
+    escrow_program: AccountInfo<'a>,
+    taker: AccountInfo<'a>,
+    taker_sending_account: AccountInfo<'a>,
+    taker_receiving_account: AccountInfo<'a>,
+    payer_receiving_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    clock_sysvar: AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::exchange(
+        escrow_program.key,
+        taker.key,
+        taker_sending_account.key,
+        taker_receiving_account.key,
+        payer_receiving_account.key,
+        temp_token_account.key,
+        rent_payer.key,
+        escrow.key,
+        amount,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            taker,
+            taker_sending_account,
+            taker_receiving_account,
+            payer_receiving_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+            token_program,
+            vault_authority,
+            clock_sysvar,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `SettleMany` on every `(escrow, temp token account, payee account, referrer account)`
+#[allow(clippy::too_many_arguments)]
+pub fn settle_many<'a>(
+    escrow_program: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    fee_taker_account: AccountInfo<'a>,
+    rent_payer: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    vault_authority: AccountInfo<'a>,
+    settlements: &[(
+        AccountInfo<'a>,
+        AccountInfo<'a>,
+        AccountInfo<'a>,
+        Option<AccountInfo<'a>>,
+    )],
+    fee_basis_points: u16,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let keys: Vec<_> = settlements
+        .iter()
+        .map(
+            |(escrow, temp_token_account, payee_account, referrer_account)| {
+                (
+                    *escrow.key,
+                    *temp_token_account.key,
+                    *payee_account.key,
+                    referrer_account.as_ref().map(|account| *account.key),
+                )
+            },
+        )
+        .collect();
+    let ix = instruction::settle_many(
+        escrow_program.key,
+        authority.key,
+        fee_taker_account.key,
+        rent_payer.key,
+        &keys,
+        fee_basis_points,
+    );
+    let mut account_infos = vec![
+        authority,
+        fee_taker_account,
+        rent_payer,
+        token_program,
+        vault_authority,
+    ];
+    for (escrow, temp_token_account, payee_account, referrer_account) in settlements {
+        account_infos.push(escrow.clone());
+        account_infos.push(temp_token_account.clone());
+        account_infos.push(payee_account.clone());
+        account_infos.extend(referrer_account.clone());
+    }
+    account_infos.push(escrow_program);
+    invoke_signed(&ix, &account_infos, signer_seeds)
+}
+
+/// Invokes `Deposit`
+#[allow(clippy::too_many_arguments)]
+pub fn deposit<'a>(
+    escrow_program: AccountInfo<'a>,
+    payer: AccountInfo<'a>,
+    payer_account: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::deposit(
+        escrow_program.key,
+        payer.key,
+        payer_account.key,
+        temp_token_account.key,
+        escrow.key,
+        amount,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            payer,
+            payer_account,
+            temp_token_account,
+            escrow,
+            token_program,
+            escrow_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Invokes `Quote`, the `SettlementQuote` being readable with `get_return_data` afterwards
+pub fn quote<'a>(
+    escrow_program: AccountInfo<'a>,
+    escrow: AccountInfo<'a>,
+    temp_token_account: AccountInfo<'a>,
+    fee: u64,
+) -> ProgramResult {
+    let ix = instruction::quote(escrow_program.key, escrow.key, temp_token_account.key, fee);
+    invoke_signed(&ix, &[escrow, temp_token_account, escrow_program], &[])
+}
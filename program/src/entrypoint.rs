@@ -1,8 +1,9 @@
 use solana_program::{
-    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg, pubkey::Pubkey,
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg,
+    program_error::PrintProgramError, pubkey::Pubkey,
 };
 
-use crate::processor::Processor;
+use crate::{error::EscrowError, processor::Processor};
 
 entrypoint!(process_instruction);
 fn process_instruction(
@@ -16,5 +17,9 @@ fn process_instruction(
         accounts.len(),
         instruction_data
     );
-    Processor::process(program_id, accounts, instruction_data)
+    if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
+        error.print::<EscrowError>();
+        return Err(error);
+    }
+    Ok(())
 }
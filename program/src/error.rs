@@ -1,30 +1,127 @@
 // inside error.rs
+use num_derive::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError, msg, program_error::PrintProgramError, program_error::ProgramError,
+};
 use thiserror::Error;
-use solana_program::program_error::ProgramError;
 
-#[derive(Error, Debug, Copy, Clone)]
+/// Errors returned by the escrow program as `ProgramError::Custom` codes.
+///
+/// The discriminants are the on-chain error codes clients decode, so existing
+/// values must never change: new variants are appended with the next number.
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
 pub enum EscrowError {
     /// Invalid instruction
     #[error("Invalid Instruction")]
-    InvalidInstruction,
+    InvalidInstruction = 0,
     #[error("No rent excemption")]
-    NotRentExempt,
+    NotRentExempt = 1,
     #[error("Amount mismatch")]
-    ExpectedAmountMismatch,
+    ExpectedAmountMismatch = 2,
     #[error("Authority is invalid")]
-    InvalidAuthorityId,
+    InvalidAuthorityId = 3,
     #[error("Amount overflow")]
-    AmountOverflow,
+    AmountOverflow = 4,
     #[error("Account already settled")]
-    AccountAlreadySettled,
+    AccountAlreadySettled = 5,
     #[error("Fee overflow")]
-    FeeOverflow,
-    #[error("Account settled")]
-    AccountNotSettled
+    FeeOverflow = 6,
+    #[error("Account not settled")]
+    AccountNotSettled = 7,
+    #[error("Escrow already accepted")]
+    EscrowAlreadyAccepted = 8,
+    #[error("Escrow not accepted")]
+    EscrowNotAccepted = 9,
+    #[error("Escrow disputed")]
+    EscrowDisputed = 10,
+    #[error("Escrow already refunded")]
+    EscrowAlreadyRefunded = 11,
+    #[error("Escrow already cancelled")]
+    EscrowAlreadyCancelled = 12,
+    #[error("Escrow expired")]
+    EscrowExpired = 13,
+    #[error("Invalid escrow status transition")]
+    InvalidStatusTransition = 14,
+    #[error("Not supported by the escrow mode")]
+    InvalidEscrowMode = 15,
+    #[error("Invalid release schedule")]
+    InvalidReleaseSchedule = 16,
+    #[error("Release not due yet")]
+    ReleaseNotDue = 17,
+    #[error("Escrow condition not met")]
+    ConditionNotMet = 18,
+    #[error("Preimage does not match the hash lock")]
+    InvalidPreimage = 19,
+    #[error("Hash lock not expired")]
+    HashLockNotExpired = 20,
+    #[error("Escrow account closed")]
+    EscrowClosed = 21,
+    #[error("Escrow account not owned by the program")]
+    InvalidEscrowOwner = 22,
+    #[error("Attestation account not owned by the program")]
+    InvalidAttestationOwner = 23,
+    #[error("PDA account does not match the escrow's")]
+    InvalidPda = 24,
+    #[error("Temp token account does not belong to the escrow")]
+    VaultMismatch = 25,
+    #[error("Account is not the escrow's rent payer")]
+    RentPayerMismatch = 26,
+    #[error("Account does not belong to the escrow's payee")]
+    PayeeMismatch = 27,
+    #[error("Account does not belong to the escrow's payer")]
+    PayerMismatch = 28,
+    #[error("New authority account does not match the instruction")]
+    NewAuthorityMismatch = 29,
+    #[error("Account is not the swap's receiving account")]
+    ReceiveAccountMismatch = 30,
+    #[error("Escrow account is not at its derived address")]
+    EscrowAddressMismatch = 31,
+    #[error("Referrer fee share above 100% or without a referrer")]
+    InvalidFeeSplit = 32,
+    #[error("Account does not belong to the escrow's fee taker")]
+    FeeTakerMismatch = 33,
+    #[error("Account does not belong to the escrow's referrer")]
+    ReferrerMismatch = 34,
+    #[error("Fee above the maximum the payer agreed to")]
+    FeeAboveMaximum = 35,
+    #[error("Config account is not the program's")]
+    InvalidConfig = 36,
+    #[error("Governance account is not the program's")]
+    InvalidGovernance = 37,
+    #[error("Signer is not the config admin")]
+    AdminMismatch = 38,
+    #[error("Opening escrows is paused")]
+    EscrowsPaused = 39,
+    #[error("Mint not allowed by the config")]
+    MintNotAllowed = 40,
+    #[error("Max fee above the configured fee rate")]
+    FeeRateAboveMaximum = 41,
+    #[error("No config change pending")]
+    NoPendingConfigChange = 42,
+    #[error("Config change delay not elapsed")]
+    ConfigChangeNotDue = 43,
+    #[error("Invalid config params")]
+    InvalidConfigParams = 44,
+    #[error("Signer is not the program's upgrade authority")]
+    InvalidUpgradeAuthority = 45,
+    #[error("Authority is not a PDA of the given program")]
+    AuthorityProgramMismatch = 46,
 }
 
 impl From<EscrowError> for ProgramError {
     fn from(e: EscrowError) -> Self {
         ProgramError::Custom(e as u32)
     }
-}
\ No newline at end of file
+}
+
+impl<T> DecodeError<T> for EscrowError {
+    fn type_of() -> &'static str {
+        "EscrowError"
+    }
+}
+
+impl PrintProgramError for EscrowError {
+    fn print<E>(&self) {
+        msg!("Escrow error {}: {}", *self as u32, self);
+    }
+}
@@ -0,0 +1,209 @@
+// inside governance.rs
+//! Program wide configuration and the timelock guarding its changes.
+//!
+//! The `Config` account holds the fee policy every new escrow is checked against. Its admin
+//! cannot change it directly: changes are proposed into the `Governance` account and only
+//! executed once the configured delay has passed, leaving everyone time to react to them.
+
+use solana_program::{
+    clock::UnixTimestamp,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use crate::state::MAX_BASIS_POINTS;
+
+/// Most mints a config can restrict escrows to
+pub const MAX_ALLOWED_MINTS: usize = 8;
+
+/// Finds the address of the program's config account
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"config"], program_id)
+}
+
+/// Finds the address of the program's governance account
+pub fn find_governance_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"governance"], program_id)
+}
+
+/// The settings of the program, applied as a whole when a change is executed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigParams {
+    /// Who proposes and cancels config changes
+    pub admin: Pubkey,
+    /// The fee taker every new escrow pays its fees to
+    pub fee_taker: Pubkey,
+    /// Highest max fee an escrow can be opened with, in basis points of its amount
+    pub max_fee_bps: u16,
+    /// Whether opening new escrows is suspended
+    pub paused: bool,
+    /// Seconds between proposing a change and being able to execute it
+    pub delay: UnixTimestamp,
+    /// Mints escrows can be opened in, any mint when empty
+    pub allowed_mints: Vec<Pubkey>,
+}
+
+impl ConfigParams {
+    pub const LEN: usize = 332;
+
+    /// Whether the params can be applied: rates within 100%, a non negative delay
+    /// and no more mints than fit in the account
+    pub fn is_valid(&self) -> bool {
+        self.max_fee_bps <= MAX_BASIS_POINTS
+            && self.delay >= 0
+            && self.allowed_mints.len() <= MAX_ALLOWED_MINTS
+    }
+
+    pub fn is_mint_allowed(&self, mint: &Pubkey) -> bool {
+        self.allowed_mints.is_empty() || self.allowed_mints.contains(mint)
+    }
+
+    pub fn unpack_from_slice(src: &[u8; ConfigParams::LEN]) -> Result<Self, ProgramError> {
+        let (admin, fee_taker, max_fee_bps, paused, delay, allowed_mints_len, allowed_mints) =
+            array_refs![src, 32, 32, 2, 1, 8, 1, 32 * MAX_ALLOWED_MINTS];
+        let paused = match paused {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let allowed_mints_len = allowed_mints_len[0] as usize;
+        if allowed_mints_len > MAX_ALLOWED_MINTS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(ConfigParams {
+            admin: Pubkey::new_from_array(*admin),
+            fee_taker: Pubkey::new_from_array(*fee_taker),
+            max_fee_bps: u16::from_le_bytes(*max_fee_bps),
+            paused,
+            delay: i64::from_le_bytes(*delay),
+            allowed_mints: allowed_mints
+                .chunks_exact(32)
+                .take(allowed_mints_len)
+                .map(|mint| Pubkey::new_from_array(*array_ref![mint, 0, 32]))
+                .collect(),
+        })
+    }
+
+    pub fn pack_into_slice(&self, dst: &mut [u8; ConfigParams::LEN]) {
+        let (
+            admin_dst,
+            fee_taker_dst,
+            max_fee_bps_dst,
+            paused_dst,
+            delay_dst,
+            allowed_mints_len_dst,
+            allowed_mints_dst,
+        ) = mut_array_refs![dst, 32, 32, 2, 1, 8, 1, 32 * MAX_ALLOWED_MINTS];
+
+        admin_dst.copy_from_slice(self.admin.as_ref());
+        fee_taker_dst.copy_from_slice(self.fee_taker.as_ref());
+        *max_fee_bps_dst = self.max_fee_bps.to_le_bytes();
+        paused_dst[0] = self.paused as u8;
+        *delay_dst = self.delay.to_le_bytes();
+        allowed_mints_len_dst[0] = self.allowed_mints.len() as u8;
+        *allowed_mints_dst = [0; 32 * MAX_ALLOWED_MINTS];
+        for (mint_dst, mint) in allowed_mints_dst
+            .chunks_exact_mut(32)
+            .zip(&self.allowed_mints)
+        {
+            mint_dst.copy_from_slice(mint.as_ref());
+        }
+    }
+}
+
+/// The program's current settings, at `find_config_address`
+pub struct Config {
+    pub is_initialized: bool,
+    pub params: ConfigParams,
+}
+
+impl Pack for Config {
+    const LEN: usize = 333;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Config::LEN];
+        let (is_initialized, params) = array_refs![src, 1, ConfigParams::LEN];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(Config {
+            is_initialized,
+            params: ConfigParams::unpack_from_slice(params)?,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Config::LEN];
+        let (is_initialized_dst, params_dst) = mut_array_refs![dst, 1, ConfigParams::LEN];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        self.params.pack_into_slice(params_dst);
+    }
+}
+
+impl Sealed for Config {}
+
+impl IsInitialized for Config {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// The config change waiting for its timelock, at `find_governance_address`
+pub struct Governance {
+    pub is_initialized: bool,
+    /// The proposed settings, replacing the config's as a whole once executed
+    pub pending: Option<ConfigParams>,
+    /// When the pending change can be executed
+    pub eta: UnixTimestamp,
+}
+
+impl Pack for Governance {
+    const LEN: usize = 342;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Governance::LEN];
+        let (is_initialized, has_pending, eta, pending) =
+            array_refs![src, 1, 1, 8, ConfigParams::LEN];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let pending = match has_pending {
+            [0] => None,
+            [1] => Some(ConfigParams::unpack_from_slice(pending)?),
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(Governance {
+            is_initialized,
+            pending,
+            eta: i64::from_le_bytes(*eta),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Governance::LEN];
+        let (is_initialized_dst, has_pending_dst, eta_dst, pending_dst) =
+            mut_array_refs![dst, 1, 1, 8, ConfigParams::LEN];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        has_pending_dst[0] = self.pending.is_some() as u8;
+        *eta_dst = self.eta.to_le_bytes();
+        match &self.pending {
+            Some(pending) => pending.pack_into_slice(pending_dst),
+            None => *pending_dst = [0; ConfigParams::LEN],
+        }
+    }
+}
+
+impl Sealed for Governance {}
+
+impl IsInitialized for Governance {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
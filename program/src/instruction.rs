@@ -1,70 +1,569 @@
 // inside instruction.rs
-use solana_program::program_error::ProgramError;
+use solana_program::{
+    bpf_loader_upgradeable,
+    clock::UnixTimestamp,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
 use std::convert::TryInto;
 
-use crate::error::EscrowError::InvalidInstruction;
+use arrayref::{array_mut_ref, array_ref};
+
+use crate::{
+    error::EscrowError::InvalidInstruction,
+    find_escrow_address,
+    governance::{find_config_address, find_governance_address, ConfigParams},
+    state::EscrowMode,
+};
+
+/// The program an escrow authority is a PDA of, with the seeds deriving it, bump included
+pub struct AuthorityProgram {
+    pub program_id: Pubkey,
+    pub seeds: Vec<Vec<u8>>,
+}
 
 pub enum EscrowInstruction {
-    /// Starts the trade by creating and populating an escrow account and transferring ownership of the given temp token account to the PDA
+    /// Starts the trade by creating and populating an escrow account and transferring ownership of the given temp token account to the PDA.
+    /// The escrow stays pending until it is accepted, and can be cancelled by the payer until then.
+    /// Payer and authority can be PDAs signing through `invoke_signed`, see `cpi`
     ///
     ///
     /// Accounts expected:
     ///
     /// 0. `[signer]` The account of the payer initializing the escrow
-    /// 1. `[signer]` The escrow authority responsible for approving / refunding payments due to some external conditions
-    /// 2. `[writable]`Temporary token account that should be created prior to this instruction and owned by the payer
-    /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade.
+    /// 1. `[writable]`Temporary token account that should be created prior to this instruction and owned by the payer
+    /// 2. `[signer]` The escrow authority responsible for approving / refunding payments due to some external conditions
+    /// 3. `[writable]` The escrow account, created by this instruction at the address derived from the payer, the authority and the reference, see `find_escrow_address`
     /// 4. `[]` The rent sysvar
     /// 5. `[]` The token program
+    /// 6. `[signer, writable]` The fee payer funding the escrow account, the only account its rent is returned to
+    /// 7. `[]` The system program
+    /// 8. `[]` The fee taker's main account, owning the accounts the fee is paid into, the config's fee taker
+    /// 9. `[]` The program's config account, see `find_config_address`
+    /// 10. `[]` (optional) The referrer's main account, paid `referrer_fee_bps` of the fee
     InitEscrow {
         /// The total amount of token X to be paid by the payer
         amount: u64,
+        /// The external order reference, the nonce the escrow address is derived from
+        reference: [u8; 32],
+        /// The referrer's share of the fee in basis points, the fee taker getting the rest
+        referrer_fee_bps: u16,
+        /// The highest fee the payer agrees to be charged on settlement, within the config's fee rate
+        max_fee: u64,
+        /// How the amount is released to the payee, `Standard` when omitted
+        mode: EscrowMode,
+        /// The program the authority is a PDA of, recorded in the escrow once its seeds are checked.
+        /// Requires an explicit mode when given
+        authority_program: Option<AuthorityProgram>,
     },
-    /// Settle the payment
+    /// Settle the payment of an accepted standard escrow to its payee, with a fee no higher than the escrow's max fee.
+    /// Escrows in other modes are only released by their own instructions
     ///
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the authority 
-    /// 1. `[writable]` The taker's token account for the token they will receive should the trade go through
-    /// 2. `[writable]` The fee taker's token account for the token they will receive should the trade go through
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The payee's account for the token they will receive should the trade go through, owned by the payee that accepted the escrow
+    /// 2. `[writable]` The fee taker's token account for the token they will receive should the trade go through, owned by the escrow's fee taker
     /// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close
-    /// 4. `[writable]` The fee payer's main account to send their rent fees to
-    /// 5. `[writable]` The escrow account holding the escrow info
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[writable]` The fee payer's main account that funded the escrow, to send their rent fees to
     /// 6. `[]` The token program
     /// 7. `[]` The PDA account
+    /// 8. `[writable]` (optional) The referrer's token account for their share of the fee, required when the escrow has a referrer
     Settle {
-        /// the amount the fee taker expects to be paid from amount
+        /// the amount the fee taker and the referrer expect to be paid from amount
         fee: u64,
     },
-    /// Close the escrow
+    /// Close the escrow, zeroing its data behind a closed marker so it can never be used again
     ///
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the authority 
+    /// 0. `[signer]` The account of the authority
     /// 1. `[writable]` The escrow account holding the escrow info     
-    /// 2. `[writable]` The fee payer's main account to send their rent fees to
+    /// 2. `[writable]` The fee payer's main account that funded the escrow, to send their rent fees to
     Close,
+    /// Rotate the escrow authority. The escrow keeps the address derived from its original authority
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the current authority
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[signer]` (optional) The account of the new authority, accepting the role
+    UpdateAuthority {
+        /// the authority that will be allowed to settle and close the escrow
+        new_authority: Pubkey,
+    },
+    /// Accept a pending escrow as its payee, committing to the trade so the payer can no longer cancel it.
+    /// Swap escrows are only taken through `Exchange`, hash locked escrows are accepted by the payee set at init alone
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the payee
+    /// 1. `[signer]` The account of the authority, vouching for the payee, not signing for hash locked escrows
+    /// 2. `[writable]` The escrow account holding the escrow info
+    /// 3. `[]` The clock sysvar
+    Accept,
+    /// Cancel a pending escrow, giving the temp token account back to the payer and closing the escrow.
+    /// Hash locked escrows are held until they expire and only get back to the payer through `Refund`
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the payer
+    /// 1. `[writable]` The PDA's temp token account to hand back to the payer
+    /// 2. `[writable]` The escrow account holding the escrow info
+    /// 3. `[]` The token program
+    /// 4. `[]` The PDA account
+    /// 5. `[writable]` The fee payer's main account that funded the escrow, to send their rent fees to
+    Cancel,
+    /// Release the next due period of a recurring escrow to its payee
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The payee's token account, owned by the payee that accepted the escrow
+    /// 2. `[writable]` The PDA's temp token account to get tokens from, closed once empty
+    /// 3. `[writable]` The fee payer's main account that funded the escrow, to send their rent fees to
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[]` The clock sysvar
+    Crank,
+    /// Withdraw the vested but not yet withdrawn part of a vesting escrow
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the payee that accepted the escrow
+    /// 1. `[writable]` The payee's token account
+    /// 2. `[writable]` The PDA's temp token account to get tokens from, closed once empty
+    /// 3. `[writable]` The main account that funded the escrow, to send the temp account's rent to
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[]` The clock sysvar
+    Withdraw,
+    /// Stop a vesting escrow, sending the part not vested yet back to the payer
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The payer's token account
+    /// 2. `[writable]` The PDA's temp token account to get tokens from, closed once empty
+    /// 3. `[writable]` The main account that funded the escrow, to send the temp account's rent to
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[]` The clock sysvar
+    Revoke,
+    /// Record an attestation for a conditional escrow, signed by the attestor
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the attestor
+    /// 1. `[writable]` The attestation account, created prior to this instruction and owned by the program
+    /// 2. `[]` The escrow account the attestation is about
+    /// 3. `[]` The rent sysvar
+    /// 4. `[]` The clock sysvar
+    Attest {
+        /// what the attestor vouches for, compared against the escrow's condition
+        payload: [u8; 32],
+    },
+    /// Settle a conditional escrow to its payee once its condition is attested, callable by anyone.
+    /// No fee is taken
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The payee's account for the token they will receive, owned by the payee that accepted the escrow
+    /// 1. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 2. `[writable]` The main account that funded the escrow, to send the temp account's rent to
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The attestation account
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    SettleByCondition,
+    /// Claim a hash locked escrow as its payee by revealing the preimage of its hash before it expires.
+    /// A pending escrow is accepted by its payee in the same instruction. No fee is taken
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the payee set at init
+    /// 1. `[writable]` The payee's account for the token they will receive
+    /// 2. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 3. `[writable]` The main account that funded the escrow, to send the temp account's rent to
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[]` The clock sysvar
+    Claim {
+        /// the secret whose SHA-256 hash locks the escrow
+        preimage: [u8; 32],
+    },
+    /// Give an expired hash locked escrow's temp token account back to the payer, whether it was accepted or not
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the payer
+    /// 1. `[writable]` The PDA's temp token account to hand back to the payer
+    /// 2. `[writable]` The escrow account holding the escrow info
+    /// 3. `[]` The token program
+    /// 4. `[]` The PDA account
+    /// 5. `[]` The clock sysvar
+    Refund,
+    /// Take a pending swap escrow, sending the expected amount of the other token to the payer
+    /// and receiving the escrowed tokens in the same instruction
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the taker
+    /// 1. `[writable]` The taker's token account sending the token the payer expects
+    /// 2. `[writable]` The taker's account for the escrowed token they will receive
+    /// 3. `[writable]` The payer's token account receiving the token they expect
+    /// 4. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 5. `[writable]` The main account that funded the escrow, to send the temp account's rent to
+    /// 6. `[writable]` The escrow account holding the escrow info
+    /// 7. `[]` The token program
+    /// 8. `[]` The PDA account
+    /// 9. `[]` The clock sysvar
+    Exchange {
+        /// the amount of escrowed tokens the taker expects to receive
+        amount: u64,
+    },
+    /// Settle several accepted escrows to their payees at once, failing if any of them can't be settled.
+    /// Escrows with a referrer need `with_referrers`, which adds their referrer's account to every escrow
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The fee taker's account for the fees of every escrow, owned by their common fee taker
+    /// 2. `[writable]` The fee payer's main account that funded the escrow, to send their rent fees to
+    /// 3. `[]` The token program
+    /// 4. `[]` The PDA account
+    /// 5. `[writable]` The escrow account of the first escrow
+    /// 6. `[writable]` The PDA's temp token account of the first escrow
+    /// 7. `[writable]` The payee's account of the first escrow
+    /// 8. `[writable]` The referrer's account of the first escrow, only with `with_referrers`. Ignored
+    ///    for an escrow without a referrer
+    /// 9. Accounts 5 to 7, or 5 to 8, repeated for every further escrow
+    SettleMany {
+        /// the fee taken from each escrow, in basis points of its amount
+        fee_basis_points: u16,
+        /// whether every escrow comes with a referrer's account, left out of the data when unset
+        with_referrers: bool,
+    },
+    /// Add tokens to a pending or accepted escrow
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the payer
+    /// 1. `[writable]` The payer's token account to take the tokens from
+    /// 2. `[writable]` The PDA's temp token account receiving the tokens
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    Deposit {
+        /// The amount of token X added to the escrow
+        amount: u64,
+    },
+    /// Compute what settling an escrow with `fee` would pay out, without changing anything.
+    /// The packed `SettlementQuote` is set as the return data, meant to be read by simulating the transaction
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account holding the escrow info
+    /// 1. `[]` The PDA's temp token account
+    Quote {
+        /// the amount the fee taker and the referrer would be paid from amount
+        fee: u64,
+    },
+    /// Create the program's config and governance accounts, by the program's upgrade authority
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The program's upgrade authority, funding the accounts
+    /// 1. `[]` The program's program data account
+    /// 2. `[writable]` The config account, created at `find_config_address`
+    /// 3. `[writable]` The governance account, created at `find_governance_address`
+    /// 4. `[]` The rent sysvar
+    /// 5. `[]` The system program
+    InitConfig {
+        /// the initial settings
+        params: ConfigParams,
+    },
+    /// Propose new settings, executable once the config's delay has passed.
+    /// Replaces the change pending, if any
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The config admin
+    /// 1. `[]` The config account
+    /// 2. `[writable]` The governance account
+    /// 3. `[]` The clock sysvar
+    ProposeConfigChange {
+        /// the settings replacing the current ones
+        params: ConfigParams,
+    },
+    /// Apply the pending config change once its delay has passed, callable by anyone
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The config account
+    /// 1. `[writable]` The governance account
+    /// 2. `[]` The clock sysvar
+    ExecuteConfigChange,
+    /// Drop the pending config change
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The config admin
+    /// 1. `[]` The config account
+    /// 2. `[writable]` The governance account
+    CancelConfigChange,
 }
 
 impl EscrowInstruction {
+    /// Packs a [EscrowInstruction](enum.EscrowInstruction.html) into a byte buffer, the inverse of `unpack`.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match self {
+            Self::InitEscrow {
+                amount,
+                reference,
+                referrer_fee_bps,
+                max_fee,
+                mode,
+                authority_program,
+            } => {
+                buf.push(0);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(reference);
+                buf.extend_from_slice(&referrer_fee_bps.to_le_bytes());
+                buf.extend_from_slice(&max_fee.to_le_bytes());
+                Self::pack_mode(mode, &mut buf);
+                if let Some(AuthorityProgram { program_id, seeds }) = authority_program {
+                    buf.extend_from_slice(program_id.as_ref());
+                    buf.push(seeds.len() as u8);
+                    for seed in seeds {
+                        buf.push(seed.len() as u8);
+                        buf.extend_from_slice(seed);
+                    }
+                }
+            }
+            Self::Settle { fee } => {
+                buf.push(1);
+                buf.extend_from_slice(&fee.to_le_bytes());
+            }
+            Self::Close => buf.push(2),
+            Self::UpdateAuthority { new_authority } => {
+                buf.push(3);
+                buf.extend_from_slice(new_authority.as_ref());
+            }
+            Self::Accept => buf.push(4),
+            Self::Cancel => buf.push(5),
+            Self::Crank => buf.push(6),
+            Self::Withdraw => buf.push(7),
+            Self::Revoke => buf.push(8),
+            Self::Attest { payload } => {
+                buf.push(9);
+                buf.extend_from_slice(payload);
+            }
+            Self::SettleByCondition => buf.push(10),
+            Self::Claim { preimage } => {
+                buf.push(11);
+                buf.extend_from_slice(preimage);
+            }
+            Self::Refund => buf.push(12),
+            Self::Exchange { amount } => {
+                buf.push(13);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SettleMany {
+                fee_basis_points,
+                with_referrers,
+            } => {
+                buf.push(14);
+                buf.extend_from_slice(&fee_basis_points.to_le_bytes());
+                if *with_referrers {
+                    buf.push(1);
+                }
+            }
+            Self::Deposit { amount } => {
+                buf.push(15);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::Quote { fee } => {
+                buf.push(16);
+                buf.extend_from_slice(&fee.to_le_bytes());
+            }
+            Self::InitConfig { params } => {
+                buf.push(17);
+                Self::pack_config_params(params, &mut buf);
+            }
+            Self::ProposeConfigChange { params } => {
+                buf.push(18);
+                Self::pack_config_params(params, &mut buf);
+            }
+            Self::ExecuteConfigChange => buf.push(19),
+            Self::CancelConfigChange => buf.push(20),
+        }
+        buf
+    }
+
+    fn pack_mode(mode: &EscrowMode, buf: &mut Vec<u8>) {
+        match mode {
+            EscrowMode::Standard => buf.push(0),
+            EscrowMode::Recurring {
+                period,
+                amount_per_period,
+                ..
+            } => {
+                buf.push(1);
+                buf.extend_from_slice(&period.to_le_bytes());
+                buf.extend_from_slice(&amount_per_period.to_le_bytes());
+            }
+            EscrowMode::Vesting {
+                start_at,
+                cliff_at,
+                end_at,
+                ..
+            } => {
+                buf.push(2);
+                buf.extend_from_slice(&start_at.to_le_bytes());
+                buf.extend_from_slice(&cliff_at.to_le_bytes());
+                buf.extend_from_slice(&end_at.to_le_bytes());
+            }
+            EscrowMode::Conditional {
+                attestor,
+                condition,
+            } => {
+                buf.push(3);
+                buf.extend_from_slice(attestor.as_ref());
+                buf.extend_from_slice(condition);
+            }
+            EscrowMode::HashLock {
+                hash,
+                expires_at,
+                payee,
+            } => {
+                buf.push(4);
+                buf.extend_from_slice(hash);
+                buf.extend_from_slice(&expires_at.to_le_bytes());
+                buf.extend_from_slice(payee.as_ref());
+            }
+            EscrowMode::Swap {
+                receive_account,
+                expected_amount,
+            } => {
+                buf.push(5);
+                buf.extend_from_slice(receive_account.as_ref());
+                buf.extend_from_slice(&expected_amount.to_le_bytes());
+            }
+        }
+    }
+
+    fn pack_config_params(params: &ConfigParams, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.resize(start + ConfigParams::LEN, 0);
+        params.pack_into_slice(array_mut_ref![buf, start, ConfigParams::LEN]);
+    }
+
     /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
 
         Ok(match tag {
-            0 => Self::InitEscrow {
-                amount: Self::unpack_amount(rest)?,
-            },
+            0 => {
+                let mode = Self::unpack_mode(rest.get(50..).unwrap_or_default())?;
+                let authority_program = rest
+                    .get(50 + Self::mode_len(&mode)..)
+                    .filter(|input| !input.is_empty())
+                    .map(Self::unpack_authority_program)
+                    .transpose()?;
+                Self::InitEscrow {
+                    amount: Self::unpack_amount(rest)?,
+                    reference: Self::unpack_bytes32(rest.get(8..).unwrap_or_default())?,
+                    referrer_fee_bps: Self::unpack_basis_points(
+                        rest.get(40..).unwrap_or_default(),
+                    )?,
+                    max_fee: Self::unpack_amount(rest.get(42..).unwrap_or_default())?,
+                    mode,
+                    authority_program,
+                }
+            }
             1 => Self::Settle {
                 fee: Self::unpack_amount(rest)?,
             },
             2 => Self::Close,
+            3 => Self::UpdateAuthority {
+                new_authority: Self::unpack_pubkey(rest)?,
+            },
+            4 => Self::Accept,
+            5 => Self::Cancel,
+            6 => Self::Crank,
+            7 => Self::Withdraw,
+            8 => Self::Revoke,
+            9 => Self::Attest {
+                payload: Self::unpack_bytes32(rest)?,
+            },
+            10 => Self::SettleByCondition,
+            11 => Self::Claim {
+                preimage: Self::unpack_bytes32(rest)?,
+            },
+            12 => Self::Refund,
+            13 => Self::Exchange {
+                amount: Self::unpack_amount(rest)?,
+            },
+            14 => Self::SettleMany {
+                fee_basis_points: Self::unpack_basis_points(rest)?,
+                with_referrers: match rest.get(2) {
+                    None => false,
+                    Some(1) => true,
+                    Some(_) => return Err(InvalidInstruction.into()),
+                },
+            },
+            15 => Self::Deposit {
+                amount: Self::unpack_amount(rest)?,
+            },
+            16 => Self::Quote {
+                fee: Self::unpack_amount(rest)?,
+            },
+            17 => Self::InitConfig {
+                params: Self::unpack_config_params(rest)?,
+            },
+            18 => Self::ProposeConfigChange {
+                params: Self::unpack_config_params(rest)?,
+            },
+            19 => Self::ExecuteConfigChange,
+            20 => Self::CancelConfigChange,
             _ => return Err(InvalidInstruction.into()),
         })
     }
 
+    fn unpack_config_params(input: &[u8]) -> Result<ConfigParams, ProgramError> {
+        let input = input.get(..ConfigParams::LEN).ok_or(InvalidInstruction)?;
+        ConfigParams::unpack_from_slice(array_ref![input, 0, ConfigParams::LEN])
+            .map_err(|_| InvalidInstruction.into())
+    }
+
     fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
         input
             .get(..8)
@@ -72,4 +571,630 @@ impl EscrowInstruction {
             .map(u64::from_le_bytes)
             .ok_or(InvalidInstruction.into())
     }
+
+    fn unpack_basis_points(input: &[u8]) -> Result<u16, ProgramError> {
+        input
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or(InvalidInstruction.into())
+    }
+
+    fn unpack_timestamp(input: &[u8]) -> Result<UnixTimestamp, ProgramError> {
+        input
+            .get(..8)
+            .and_then(|slice| slice.try_into().ok())
+            .map(i64::from_le_bytes)
+            .ok_or(InvalidInstruction.into())
+    }
+
+    fn unpack_mode(input: &[u8]) -> Result<EscrowMode, ProgramError> {
+        let (tag, rest) = match input.split_first() {
+            Some(split) => split,
+            None => return Ok(EscrowMode::Standard),
+        };
+
+        Ok(match tag {
+            0 => EscrowMode::Standard,
+            1 => EscrowMode::Recurring {
+                period: Self::unpack_timestamp(rest)?,
+                amount_per_period: Self::unpack_amount(rest.get(8..).unwrap_or_default())?,
+                released_periods: 0,
+            },
+            2 => EscrowMode::Vesting {
+                start_at: Self::unpack_timestamp(rest)?,
+                cliff_at: Self::unpack_timestamp(rest.get(8..).unwrap_or_default())?,
+                end_at: Self::unpack_timestamp(rest.get(16..).unwrap_or_default())?,
+                released: 0,
+            },
+            3 => EscrowMode::Conditional {
+                attestor: Self::unpack_pubkey(rest)?,
+                condition: Self::unpack_bytes32(rest.get(32..).unwrap_or_default())?,
+            },
+            4 => EscrowMode::HashLock {
+                hash: Self::unpack_bytes32(rest)?,
+                expires_at: Self::unpack_timestamp(rest.get(32..).unwrap_or_default())?,
+                payee: Self::unpack_pubkey(rest.get(40..).unwrap_or_default())?,
+            },
+            5 => EscrowMode::Swap {
+                receive_account: Self::unpack_pubkey(rest)?,
+                expected_amount: Self::unpack_amount(rest.get(32..).unwrap_or_default())?,
+            },
+            _ => return Err(InvalidInstruction.into()),
+        })
+    }
+
+    /// Length of an explicitly packed mode, tag included
+    fn mode_len(mode: &EscrowMode) -> usize {
+        1 + match mode {
+            EscrowMode::Standard => 0,
+            EscrowMode::Recurring { .. } => 16,
+            EscrowMode::Vesting { .. } => 24,
+            EscrowMode::Conditional { .. } => 64,
+            EscrowMode::HashLock { .. } => 72,
+            EscrowMode::Swap { .. } => 40,
+        }
+    }
+
+    /// Unpacks the program id followed by a count of seeds, each prefixed by its length
+    fn unpack_authority_program(input: &[u8]) -> Result<AuthorityProgram, ProgramError> {
+        let program_id = Self::unpack_pubkey(input)?;
+        let (count, mut rest) = input
+            .get(32..)
+            .and_then(|rest| rest.split_first())
+            .ok_or(InvalidInstruction)?;
+        let mut seeds = Vec::with_capacity(*count as usize);
+        for _ in 0..*count {
+            let (len, tail) = rest.split_first().ok_or(InvalidInstruction)?;
+            let seed = tail.get(..*len as usize).ok_or(InvalidInstruction)?;
+            seeds.push(seed.to_vec());
+            rest = &tail[seed.len()..];
+        }
+        Ok(AuthorityProgram { program_id, seeds })
+    }
+
+    fn unpack_pubkey(input: &[u8]) -> Result<Pubkey, ProgramError> {
+        input
+            .get(..32)
+            .and_then(|slice| slice.try_into().ok())
+            .map(Pubkey::new_from_array)
+            .ok_or(InvalidInstruction.into())
+    }
+
+    fn unpack_bytes32(input: &[u8]) -> Result<[u8; 32], ProgramError> {
+        input
+            .get(..32)
+            .and_then(|slice| slice.try_into().ok())
+            .ok_or(InvalidInstruction.into())
+    }
+}
+
+/// The PDA owning every temp token account
+fn vault_authority(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"escrow"], program_id).0
+}
+
+/// Creates an `InitEscrow` instruction, the escrow account being derived from the payer,
+/// the authority and the reference
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    temp_token_account: &Pubkey,
+    authority: &Pubkey,
+    rent_payer: &Pubkey,
+    fee_taker: &Pubkey,
+    referrer: Option<&Pubkey>,
+    amount: u64,
+    reference: [u8; 32],
+    referrer_fee_bps: u16,
+    max_fee: u64,
+    mode: EscrowMode,
+    authority_program: Option<AuthorityProgram>,
+) -> Instruction {
+    let (escrow, _) = find_escrow_address(payer, authority, &reference, program_id);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*payer, true),
+        AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(escrow, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(*rent_payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*fee_taker, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+    ];
+    if let Some(referrer) = referrer {
+        accounts.push(AccountMeta::new_readonly(*referrer, false));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::InitEscrow {
+            amount,
+            reference,
+            referrer_fee_bps,
+            max_fee,
+            mode,
+            authority_program,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `Settle` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn settle(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payee_account: &Pubkey,
+    fee_taker_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    escrow: &Pubkey,
+    rent_payer: &Pubkey,
+    referrer_account: Option<&Pubkey>,
+    fee: u64,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*payee_account, false),
+        AccountMeta::new(*fee_taker_account, false),
+        AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new(*rent_payer, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(vault_authority(program_id), false),
+    ];
+    if let Some(referrer_account) = referrer_account {
+        accounts.push(AccountMeta::new(*referrer_account, false));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Settle { fee }.pack(),
+    }
+}
+
+/// Creates a `Close` instruction
+pub fn close(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    rent_payer: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new(*rent_payer, false),
+        ],
+        data: EscrowInstruction::Close.pack(),
+    }
+}
+
+/// Creates an `UpdateAuthority` instruction, with the new authority signing when `accept` is set
+pub fn update_authority(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    new_authority: &Pubkey,
+    accept: bool,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*escrow, false),
+    ];
+    if accept {
+        accounts.push(AccountMeta::new_readonly(*new_authority, true));
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::UpdateAuthority {
+            new_authority: *new_authority,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `Accept` instruction
+pub fn accept(
+    program_id: &Pubkey,
+    payee: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*payee, true),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+        ],
+        data: EscrowInstruction::Accept.pack(),
+    }
+}
+
+/// Creates a `Cancel` instruction
+pub fn cancel(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    temp_token_account: &Pubkey,
+    escrow: &Pubkey,
+    rent_payer: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(vault_authority(program_id), false),
+            AccountMeta::new(*rent_payer, false),
+        ],
+        data: EscrowInstruction::Cancel.pack(),
+    }
+}
+
+/// Accounts shared by `Crank`, `Withdraw` and `Revoke`, which differ by who signs and receives
+fn release_accounts(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    destination: &Pubkey,
+    temp_token_account: &Pubkey,
+    rent_payer: &Pubkey,
+    escrow: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new_readonly(*signer, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new(*rent_payer, false),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(vault_authority(program_id), false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ]
+}
+
+/// Creates a `Crank` instruction
+pub fn crank(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payee_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    rent_payer: &Pubkey,
+    escrow: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: release_accounts(
+            program_id,
+            authority,
+            payee_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+        ),
+        data: EscrowInstruction::Crank.pack(),
+    }
+}
+
+/// Creates a `Withdraw` instruction
+pub fn withdraw(
+    program_id: &Pubkey,
+    payee: &Pubkey,
+    payee_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    rent_payer: &Pubkey,
+    escrow: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: release_accounts(
+            program_id,
+            payee,
+            payee_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+        ),
+        data: EscrowInstruction::Withdraw.pack(),
+    }
+}
+
+/// Creates a `Revoke` instruction
+pub fn revoke(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    rent_payer: &Pubkey,
+    escrow: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: release_accounts(
+            program_id,
+            authority,
+            payer_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+        ),
+        data: EscrowInstruction::Revoke.pack(),
+    }
+}
+
+/// Creates an `Attest` instruction
+pub fn attest(
+    program_id: &Pubkey,
+    attestor: &Pubkey,
+    attestation: &Pubkey,
+    escrow: &Pubkey,
+    payload: [u8; 32],
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*attestor, true),
+            AccountMeta::new(*attestation, false),
+            AccountMeta::new_readonly(*escrow, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+        ],
+        data: EscrowInstruction::Attest { payload }.pack(),
+    }
+}
+
+/// Creates a `SettleByCondition` instruction
+pub fn settle_by_condition(
+    program_id: &Pubkey,
+    payee_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    rent_payer: &Pubkey,
+    escrow: &Pubkey,
+    attestation: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payee_account, false),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*rent_payer, false),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(*attestation, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(vault_authority(program_id), false),
+        ],
+        data: EscrowInstruction::SettleByCondition.pack(),
+    }
+}
+
+/// Creates a `Claim` instruction
+pub fn claim(
+    program_id: &Pubkey,
+    payee: &Pubkey,
+    payee_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    rent_payer: &Pubkey,
+    escrow: &Pubkey,
+    preimage: [u8; 32],
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: release_accounts(
+            program_id,
+            payee,
+            payee_account,
+            temp_token_account,
+            rent_payer,
+            escrow,
+        ),
+        data: EscrowInstruction::Claim { preimage }.pack(),
+    }
+}
+
+/// Creates a `Refund` instruction
+pub fn refund(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    temp_token_account: &Pubkey,
+    escrow: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(vault_authority(program_id), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+        ],
+        data: EscrowInstruction::Refund.pack(),
+    }
+}
+
+/// Creates an `Exchange` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn exchange(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_sending_account: &Pubkey,
+    taker_receiving_account: &Pubkey,
+    payer_receiving_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    rent_payer: &Pubkey,
+    escrow: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*taker, true),
+            AccountMeta::new(*taker_sending_account, false),
+            AccountMeta::new(*taker_receiving_account, false),
+            AccountMeta::new(*payer_receiving_account, false),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*rent_payer, false),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(vault_authority(program_id), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+        ],
+        data: EscrowInstruction::Exchange { amount }.pack(),
+    }
+}
+
+/// Creates a `SettleMany` instruction settling every `(escrow, temp token account, payee account,
+/// referrer account)`, the referrer's accounts only being passed when one of the escrows has any
+pub fn settle_many(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    fee_taker_account: &Pubkey,
+    rent_payer: &Pubkey,
+    settlements: &[(Pubkey, Pubkey, Pubkey, Option<Pubkey>)],
+    fee_basis_points: u16,
+) -> Instruction {
+    let with_referrers = settlements
+        .iter()
+        .any(|(_, _, _, referrer_account)| referrer_account.is_some());
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*fee_taker_account, false),
+        AccountMeta::new(*rent_payer, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(vault_authority(program_id), false),
+    ];
+    for (escrow, temp_token_account, payee_account, referrer_account) in settlements {
+        accounts.push(AccountMeta::new(*escrow, false));
+        accounts.push(AccountMeta::new(*temp_token_account, false));
+        accounts.push(AccountMeta::new(*payee_account, false));
+        if with_referrers {
+            // escrows without a referrer ignore the account, their payee's is passed again
+            accounts.push(AccountMeta::new(
+                referrer_account.unwrap_or(*payee_account),
+                false,
+            ));
+        }
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SettleMany {
+            fee_basis_points,
+            with_referrers,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `Deposit` instruction
+pub fn deposit(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    payer_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    escrow: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new(*payer_account, false),
+            AccountMeta::new(*temp_token_account, false),
+            AccountMeta::new(*escrow, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: EscrowInstruction::Deposit { amount }.pack(),
+    }
+}
+
+/// Creates a `Quote` instruction
+pub fn quote(
+    program_id: &Pubkey,
+    escrow: &Pubkey,
+    temp_token_account: &Pubkey,
+    fee: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*escrow, false),
+            AccountMeta::new_readonly(*temp_token_account, false),
+        ],
+        data: EscrowInstruction::Quote { fee }.pack(),
+    }
+}
+
+/// Creates an `InitConfig` instruction
+pub fn init_config(
+    program_id: &Pubkey,
+    upgrade_authority: &Pubkey,
+    params: ConfigParams,
+) -> Instruction {
+    let (program_data, _) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*upgrade_authority, true),
+            AccountMeta::new_readonly(program_data, false),
+            AccountMeta::new(find_config_address(program_id).0, false),
+            AccountMeta::new(find_governance_address(program_id).0, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EscrowInstruction::InitConfig { params }.pack(),
+    }
+}
+
+/// Creates a `ProposeConfigChange` instruction
+pub fn propose_config_change(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    params: ConfigParams,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+            AccountMeta::new(find_governance_address(program_id).0, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+        ],
+        data: EscrowInstruction::ProposeConfigChange { params }.pack(),
+    }
+}
+
+/// Creates an `ExecuteConfigChange` instruction
+pub fn execute_config_change(program_id: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(find_config_address(program_id).0, false),
+            AccountMeta::new(find_governance_address(program_id).0, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+        ],
+        data: EscrowInstruction::ExecuteConfigChange.pack(),
+    }
+}
+
+/// Creates a `CancelConfigChange` instruction
+pub fn cancel_config_change(program_id: &Pubkey, admin: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+            AccountMeta::new(find_governance_address(program_id).0, false),
+        ],
+        data: EscrowInstruction::CancelConfigChange.pack(),
+    }
 }
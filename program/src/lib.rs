@@ -1,8 +1,12 @@
 pub mod error;
+pub mod governance;
 pub mod instruction;
 pub mod processor;
 pub mod state;
 
+#[cfg(feature = "no-entrypoint")]
+pub mod cpi;
+
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 
@@ -12,6 +16,22 @@ use solana_program::{
 
 declare_id!("My11111111111111111111111111111111111111111");
 
+/// Finds the address of the escrow a payer opens under an authority, `reference` acting as the
+/// nonce telling apart their escrows. Only that payer and authority can create it. The address
+/// stays derived from the authority the escrow was created under: once rotated with
+/// `UpdateAuthority`, the escrow is only found again from that original authority
+pub fn find_escrow_address(
+    payer: &Pubkey,
+    authority: &Pubkey,
+    reference: &[u8; 32],
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"escrow", payer.as_ref(), authority.as_ref(), reference],
+        program_id,
+    )
+}
+
 /// Checks that the supplied authority ID is the correct one for SPL-token
 pub fn check_authority_account(escrow_authority_id: &Pubkey) -> ProgramResult {
     if escrow_authority_id != &id() {
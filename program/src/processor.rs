@@ -1,20 +1,36 @@
 use crate::{
     error::EscrowError::{
-        AccountAlreadySettled, AccountNotSettled, AmountOverflow, ExpectedAmountMismatch,
-        FeeOverflow, NotRentExempt,
+        AccountAlreadySettled, AccountNotSettled, AdminMismatch, AmountOverflow,
+        AuthorityProgramMismatch, ConditionNotMet, ConfigChangeNotDue, EscrowAddressMismatch,
+        EscrowAlreadyAccepted, EscrowAlreadyCancelled, EscrowAlreadyRefunded, EscrowClosed,
+        EscrowDisputed, EscrowExpired, EscrowNotAccepted, EscrowsPaused, ExpectedAmountMismatch,
+        FeeAboveMaximum, FeeOverflow, FeeRateAboveMaximum, FeeTakerMismatch, HashLockNotExpired,
+        InvalidAttestationOwner, InvalidAuthorityId, InvalidConfig, InvalidConfigParams,
+        InvalidEscrowMode, InvalidEscrowOwner, InvalidFeeSplit, InvalidGovernance, InvalidPda,
+        InvalidPreimage, InvalidReleaseSchedule, InvalidStatusTransition, InvalidUpgradeAuthority,
+        MintNotAllowed, NewAuthorityMismatch, NoPendingConfigChange, NotRentExempt, PayeeMismatch,
+        PayerMismatch, ReceiveAccountMismatch, ReferrerMismatch, ReleaseNotDue, RentPayerMismatch,
+        VaultMismatch,
     },
-    instruction::EscrowInstruction,
-    state::Escrow,
+    find_escrow_address,
+    governance::{find_config_address, find_governance_address, Config, ConfigParams, Governance},
+    instruction::{AuthorityProgram, EscrowInstruction},
+    state::{Attestation, Escrow, EscrowMode, EscrowStatus, SettlementQuote, MAX_BASIS_POINTS},
 };
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     entrypoint::ProgramResult,
+    hash::hash,
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
-    program_pack::{IsInitialized, Pack},
+    program_memory::sol_memset,
+    program_pack::Pack,
+    program_utils::limited_deserialize,
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar},
+    system_instruction,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 use spl_token::state::Account as TokenAccount;
 
@@ -28,9 +44,25 @@ impl Processor {
         let instruction = EscrowInstruction::unpack(instruction_data)?;
 
         match instruction {
-            EscrowInstruction::InitEscrow { amount } => {
+            EscrowInstruction::InitEscrow {
+                amount,
+                reference,
+                referrer_fee_bps,
+                max_fee,
+                mode,
+                authority_program,
+            } => {
                 msg!("Instruction: InitEscrow");
-                Self::process_init_escrow(accounts, amount, program_id)
+                Self::process_init_escrow(
+                    accounts,
+                    amount,
+                    reference,
+                    referrer_fee_bps,
+                    max_fee,
+                    mode,
+                    authority_program,
+                    program_id,
+                )
             }
             EscrowInstruction::Settle { fee } => {
                 msg!("Instruction: Settle");
@@ -40,12 +72,408 @@ impl Processor {
                 msg!("Instruction: Close");
                 Self::process_close(accounts, program_id)
             }
+            EscrowInstruction::UpdateAuthority { new_authority } => {
+                msg!("Instruction: UpdateAuthority");
+                Self::process_update_authority(accounts, &new_authority, program_id)
+            }
+            EscrowInstruction::Accept => {
+                msg!("Instruction: Accept");
+                Self::process_accept(accounts, program_id)
+            }
+            EscrowInstruction::Cancel => {
+                msg!("Instruction: Cancel");
+                Self::process_cancel(accounts, program_id)
+            }
+            EscrowInstruction::Crank => {
+                msg!("Instruction: Crank");
+                Self::process_crank(accounts, program_id)
+            }
+            EscrowInstruction::Withdraw => {
+                msg!("Instruction: Withdraw");
+                Self::process_withdraw(accounts, program_id)
+            }
+            EscrowInstruction::Revoke => {
+                msg!("Instruction: Revoke");
+                Self::process_revoke(accounts, program_id)
+            }
+            EscrowInstruction::Attest { payload } => {
+                msg!("Instruction: Attest");
+                Self::process_attest(accounts, payload, program_id)
+            }
+            EscrowInstruction::SettleByCondition => {
+                msg!("Instruction: SettleByCondition");
+                Self::process_settle_by_condition(accounts, program_id)
+            }
+            EscrowInstruction::Claim { preimage } => {
+                msg!("Instruction: Claim");
+                Self::process_claim(accounts, &preimage, program_id)
+            }
+            EscrowInstruction::Refund => {
+                msg!("Instruction: Refund");
+                Self::process_refund(accounts, program_id)
+            }
+            EscrowInstruction::Exchange { amount } => {
+                msg!("Instruction: Exchange");
+                Self::process_exchange(accounts, amount, program_id)
+            }
+            EscrowInstruction::SettleMany {
+                fee_basis_points,
+                with_referrers,
+            } => {
+                msg!("Instruction: SettleMany");
+                Self::process_settle_many(accounts, fee_basis_points, with_referrers, program_id)
+            }
+            EscrowInstruction::Deposit { amount } => {
+                msg!("Instruction: Deposit");
+                Self::process_deposit(accounts, amount, program_id)
+            }
+            EscrowInstruction::Quote { fee } => {
+                msg!("Instruction: Quote");
+                Self::process_quote(accounts, fee, program_id)
+            }
+            EscrowInstruction::InitConfig { params } => {
+                msg!("Instruction: InitConfig");
+                Self::process_init_config(accounts, params, program_id)
+            }
+            EscrowInstruction::ProposeConfigChange { params } => {
+                msg!("Instruction: ProposeConfigChange");
+                Self::process_propose_config_change(accounts, params, program_id)
+            }
+            EscrowInstruction::ExecuteConfigChange => {
+                msg!("Instruction: ExecuteConfigChange");
+                Self::process_execute_config_change(accounts, program_id)
+            }
+            EscrowInstruction::CancelConfigChange => {
+                msg!("Instruction: CancelConfigChange");
+                Self::process_cancel_config_change(accounts, program_id)
+            }
+        }
+    }
+
+    /// Moves the escrow to `next`, rejecting any move the lifecycle does not allow
+    fn transition(escrow_info: &mut Escrow, next: EscrowStatus) -> ProgramResult {
+        use EscrowStatus::*;
+
+        match (escrow_info.status, next) {
+            (Uninitialized, Pending)
+            | (Pending, Active)
+            | (Pending, Cancelled)
+            | (Pending, Refunded)
+            | (Active, Disputed)
+            | (Active, Settled)
+            | (Active, Refunded)
+            | (Active, Expired)
+            | (Disputed, Settled)
+            | (Disputed, Refunded) => {}
+            (Uninitialized, _) => return Err(ProgramError::UninitializedAccount),
+            (_, Pending) => return Err(ProgramError::AccountAlreadyInitialized),
+            (Active, Active) | (Active, Cancelled) => return Err(EscrowAlreadyAccepted.into()),
+            (Active, _) => return Err(InvalidStatusTransition.into()),
+            (status, _) => return Err(Self::status_error(status)),
+        }
+
+        escrow_info.status = next;
+        Ok(())
+    }
+
+    /// Checks `pda_account` against the PDA rebuilt from the bump cached in the escrow at init,
+    /// returning the bump to sign with
+    fn check_pda(
+        escrow_info: &Escrow,
+        pda_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<u8, ProgramError> {
+        let pda =
+            Pubkey::create_program_address(&[b"escrow", &[escrow_info.bump_seed]], program_id)?;
+        if pda != *pda_account.key {
+            return Err(InvalidPda.into());
+        }
+        Ok(escrow_info.bump_seed)
+    }
+
+    /// Rejects escrows released by their own instructions, which `Settle` would bypass
+    fn ensure_standard(escrow_info: &Escrow) -> ProgramResult {
+        if escrow_info.mode != EscrowMode::Standard {
+            msg!("Only standard escrows are settled by their authority");
+            return Err(InvalidEscrowMode.into());
+        }
+        Ok(())
+    }
+
+    /// Rejects escrows that are not accepted and waiting for their payout
+    fn ensure_active(escrow_info: &Escrow) -> ProgramResult {
+        match escrow_info.status {
+            EscrowStatus::Active => Ok(()),
+            status => Err(Self::status_error(status)),
+        }
+    }
+
+    fn status_error(status: EscrowStatus) -> ProgramError {
+        match status {
+            EscrowStatus::Uninitialized => ProgramError::UninitializedAccount,
+            EscrowStatus::Pending => EscrowNotAccepted.into(),
+            EscrowStatus::Active => InvalidStatusTransition.into(),
+            EscrowStatus::Disputed => EscrowDisputed.into(),
+            EscrowStatus::Settled => AccountAlreadySettled.into(),
+            EscrowStatus::Refunded => EscrowAlreadyRefunded.into(),
+            EscrowStatus::Cancelled => EscrowAlreadyCancelled.into(),
+            EscrowStatus::Expired => EscrowExpired.into(),
+            EscrowStatus::Closed => EscrowClosed.into(),
+        }
+    }
+
+    /// Moves `amount` tokens out of the PDA owned temp token account
+    fn transfer_from_vault<'a>(
+        token_program: &AccountInfo<'a>,
+        pdas_temp_token_account: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        bump_seed: u8,
+        amount: u64,
+    ) -> ProgramResult {
+        let transfer_ix = spl_token::instruction::transfer(
+            token_program.key,
+            pdas_temp_token_account.key,
+            destination.key,
+            pda_account.key,
+            &[pda_account.key],
+            amount,
+        )?;
+        invoke_signed(
+            &transfer_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                destination.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], &[bump_seed]]],
+        )
+    }
+
+    /// Closes the PDA owned temp token account, sending its lamports to `destination`
+    fn close_vault<'a>(
+        token_program: &AccountInfo<'a>,
+        pdas_temp_token_account: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        bump_seed: u8,
+    ) -> ProgramResult {
+        let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
+            token_program.key,
+            pdas_temp_token_account.key,
+            destination.key,
+            pda_account.key,
+            &[pda_account.key],
+        )?;
+        invoke_signed(
+            &close_pdas_temp_acc_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                destination.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], &[bump_seed]]],
+        )
+    }
+
+    /// Hands the PDA owned temp token account, and the tokens and rent it holds, back to the payer
+    fn return_vault<'a>(
+        token_program: &AccountInfo<'a>,
+        pdas_temp_token_account: &AccountInfo<'a>,
+        payer_account: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        bump_seed: u8,
+    ) -> ProgramResult {
+        let owner_change_ix = spl_token::instruction::set_authority(
+            token_program.key,
+            pdas_temp_token_account.key,
+            Some(payer_account.key),
+            spl_token::instruction::AuthorityType::AccountOwner,
+            pda_account.key,
+            &[pda_account.key],
+        )?;
+        invoke_signed(
+            &owner_change_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], &[bump_seed]]],
+        )
+    }
+
+    /// Pays the whole temp token account out, each of `fees` to its recipient and the rest to the taker,
+    /// and closes it. Native accounts are closed into the escrow account first and paid out in lamports
+    #[allow(clippy::too_many_arguments)]
+    fn release_vault<'a>(
+        token_program: &AccountInfo<'a>,
+        pdas_temp_token_account: &AccountInfo<'a>,
+        pdas_temp_token_account_info: &TokenAccount,
+        escrow_account: &AccountInfo<'a>,
+        takers_account: &AccountInfo<'a>,
+        fees: &[(&AccountInfo<'a>, u64)],
+        rent_account: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        bump_seed: u8,
+    ) -> ProgramResult {
+        let fees = fees.iter().filter(|(_, fee)| *fee > 0);
+        let amount = fees
+            .clone()
+            .try_fold(pdas_temp_token_account_info.amount, |amount, (_, fee)| {
+                amount.checked_sub(*fee)
+            })
+            .ok_or(FeeOverflow)?;
+
+        if pdas_temp_token_account_info.is_native() {
+            msg!("Calling the token program to close pda's temp account...and add the remaining lamports to the escrow account");
+            Self::close_vault(
+                token_program,
+                pdas_temp_token_account,
+                escrow_account,
+                pda_account,
+                bump_seed,
+            )?;
+            for (destination, lamports) in
+                std::iter::once((takers_account, amount)).chain(fees.copied())
+            {
+                let source_starting_lamports = escrow_account.lamports();
+                **escrow_account.lamports.borrow_mut() = source_starting_lamports
+                    .checked_sub(lamports)
+                    .ok_or(AmountOverflow)?;
+
+                let dest_starting_lamports = destination.lamports();
+                **destination.lamports.borrow_mut() = dest_starting_lamports
+                    .checked_add(lamports)
+                    .ok_or(AmountOverflow)?;
+            }
+        } else {
+            msg!("Calling the token program to transfer tokens to the taker...");
+            Self::transfer_from_vault(
+                token_program,
+                pdas_temp_token_account,
+                takers_account,
+                pda_account,
+                bump_seed,
+                amount,
+            )?;
+            for (fee_account, fee) in fees {
+                msg!(
+                    "Calling the token program to transfer the fee to {}...",
+                    fee_account.key
+                );
+                Self::transfer_from_vault(
+                    token_program,
+                    pdas_temp_token_account,
+                    fee_account,
+                    pda_account,
+                    bump_seed,
+                    *fee,
+                )?;
+            }
+
+            msg!("Calling the token program to close pda's temp account...");
+            Self::close_vault(
+                token_program,
+                pdas_temp_token_account,
+                rent_account,
+                pda_account,
+                bump_seed,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Drains the escrow account's lamports to the rent payer and zeroes its data behind the
+    /// `Closed` discriminator, so it cannot be revived with stale data in the same transaction
+    fn close_escrow(
+        escrow_account: &AccountInfo,
+        rent_payer_account: &AccountInfo,
+    ) -> ProgramResult {
+        msg!("Closing the escrow account...");
+        **rent_payer_account.lamports.borrow_mut() = rent_payer_account
+            .lamports()
+            .checked_add(escrow_account.lamports())
+            .ok_or(AmountOverflow)?;
+        **escrow_account.lamports.borrow_mut() = 0;
+
+        let mut data = escrow_account.data.borrow_mut();
+        sol_memset(&mut data, 0, Escrow::LEN);
+        data[0] = EscrowStatus::Closed as u8;
+        Ok(())
+    }
+
+    /// Creates a rent exempt account of `space` bytes at a PDA of the program, funded by the rent
+    /// payer. Lamports already sent to the address are kept, so pre-funding it cannot block the creation
+    fn create_pda_account<'a>(
+        account: &AccountInfo<'a>,
+        rent_payer_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        rent: &Rent,
+        space: usize,
+        signer_seeds: &[&[u8]],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let required_lamports = rent
+            .minimum_balance(space)
+            .saturating_sub(account.lamports());
+
+        if account.lamports() == 0 {
+            return invoke_signed(
+                &system_instruction::create_account(
+                    rent_payer_account.key,
+                    account.key,
+                    required_lamports,
+                    space as u64,
+                    program_id,
+                ),
+                &[
+                    rent_payer_account.clone(),
+                    account.clone(),
+                    system_program.clone(),
+                ],
+                &[signer_seeds],
+            );
         }
+
+        if required_lamports > 0 {
+            invoke(
+                &system_instruction::transfer(
+                    rent_payer_account.key,
+                    account.key,
+                    required_lamports,
+                ),
+                &[
+                    rent_payer_account.clone(),
+                    account.clone(),
+                    system_program.clone(),
+                ],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(account.key, space as u64),
+            &[account.clone(), system_program.clone()],
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(account.key, program_id),
+            &[account.clone(), system_program.clone()],
+            &[signer_seeds],
+        )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn process_init_escrow(
         accounts: &[AccountInfo],
         amount: u64,
+        reference: [u8; 32],
+        referrer_fee_bps: u16,
+        max_fee: u64,
+        mode: EscrowMode,
+        authority_program: Option<AuthorityProgram>,
         program_id: &Pubkey,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
@@ -71,41 +499,136 @@ impl Processor {
             return Err(ExpectedAmountMismatch.into());
         }
 
+        let valid_schedule = match mode {
+            EscrowMode::Standard | EscrowMode::Conditional { .. } => true,
+            EscrowMode::HashLock { payee, .. } => payee != Pubkey::default(),
+            EscrowMode::Recurring {
+                period,
+                amount_per_period,
+                ..
+            } => period > 0 && amount_per_period > 0,
+            EscrowMode::Vesting {
+                start_at,
+                cliff_at,
+                end_at,
+                ..
+            } => start_at <= cliff_at && cliff_at <= end_at && start_at < end_at,
+            EscrowMode::Swap {
+                expected_amount, ..
+            } => expected_amount > 0,
+        };
+        if !valid_schedule {
+            return Err(InvalidReleaseSchedule.into());
+        }
+
         let authority = next_account_info(account_info_iter)?;
         if !authority.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
+        if let Some(AuthorityProgram {
+            program_id: authority_program_id,
+            seeds,
+        }) = &authority_program
+        {
+            let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+            if Pubkey::create_program_address(&seeds, authority_program_id) != Ok(*authority.key) {
+                return Err(AuthorityProgramMismatch.into());
+            }
+        }
 
         let escrow_account = next_account_info(account_info_iter)?;
         let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let rent_payer_account = next_account_info(account_info_iter)?;
+        if !rent_payer_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let system_program = next_account_info(account_info_iter)?;
+        let fee_taker = next_account_info(account_info_iter)?;
+        let config = Self::load_config(next_account_info(account_info_iter)?, program_id)?;
+        let referrer = next_account_info(account_info_iter).ok();
+        if referrer_fee_bps > MAX_BASIS_POINTS || (referrer.is_none() && referrer_fee_bps > 0) {
+            return Err(InvalidFeeSplit.into());
+        }
 
-        if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
-            return Err(NotRentExempt.into());
+        if config.params.paused {
+            return Err(EscrowsPaused.into());
+        }
+        if !config
+            .params
+            .is_mint_allowed(&payer_temp_token_account_info.mint)
+        {
+            return Err(MintNotAllowed.into());
+        }
+        if config.params.fee_taker != *fee_taker.key {
+            return Err(FeeTakerMismatch.into());
+        }
+        if max_fee as u128 * MAX_BASIS_POINTS as u128
+            > amount as u128 * config.params.max_fee_bps as u128
+        {
+            msg!(
+                "Max fee too high..., {} is above {} basis points of {}",
+                max_fee,
+                config.params.max_fee_bps,
+                amount
+            );
+            return Err(FeeRateAboveMaximum.into());
         }
 
-        let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.data.borrow())?;
-        if escrow_info.is_initialized() {
-            return Err(ProgramError::AccountAlreadyInitialized);
+        let (escrow_address, escrow_bump_seed) =
+            find_escrow_address(payer_account.key, authority.key, &reference, program_id);
+        if escrow_address != *escrow_account.key {
+            return Err(EscrowAddressMismatch.into());
         }
+        msg!("Creating the escrow account...");
+        Self::create_pda_account(
+            escrow_account,
+            rent_payer_account,
+            system_program,
+            rent,
+            Escrow::LEN,
+            &[
+                b"escrow",
+                payer_account.key.as_ref(),
+                authority.key.as_ref(),
+                &reference,
+                &[escrow_bump_seed],
+            ],
+            program_id,
+        )?;
+
+        let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.data.borrow())?;
+        Self::transition(&mut escrow_info, EscrowStatus::Pending)?;
 
-        escrow_info.is_initialized = true;
-        escrow_info.is_settled = false;
         escrow_info.payer_pubkey = *payer_account.key;
         escrow_info.payer_temp_token_account_pubkey = *payer_temp_token_account.key;
         escrow_info.authority_pubkey = *authority.key;
         escrow_info.amount = amount;
+        escrow_info.reference = reference;
+        escrow_info.mode = mode;
+        escrow_info.rent_payer_pubkey = *rent_payer_account.key;
+        escrow_info.fee_taker_pubkey = *fee_taker.key;
+        escrow_info.max_fee = max_fee;
+        if let Some(authority_program) = authority_program {
+            escrow_info.authority_program_id = authority_program.program_id;
+        }
+        if let Some(referrer) = referrer {
+            escrow_info.referrer_pubkey = *referrer.key;
+            escrow_info.referrer_fee_bps = referrer_fee_bps;
+        }
+
+        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        escrow_info.bump_seed = bump_seed;
 
         Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
-        let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
 
-        let token_program = next_account_info(account_info_iter)?;
         let owner_change_ix = spl_token::instruction::set_authority(
             token_program.key,
             payer_temp_token_account.key,
             Some(&pda),
             spl_token::instruction::AuthorityType::AccountOwner,
             payer_account.key,
-            &[&payer_account.key],
+            &[payer_account.key],
         )?;
 
         msg!("Calling the token program to transfer token account ownership...");
@@ -136,153 +659,209 @@ impl Processor {
 
         let takers_account = next_account_info(account_info_iter)?;
         let fee_taker_account = next_account_info(account_info_iter)?;
-
         let pdas_temp_token_account = next_account_info(account_info_iter)?;
-        let pdas_temp_token_account_info =
-            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
-
         let escrow_account = next_account_info(account_info_iter)?;
-        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        let fee_payer_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let referrer_account = next_account_info(account_info_iter).ok();
 
-        if escrow_info.is_settled() {
-            return Err(AccountAlreadySettled.into());
-        }
-        if escrow_info.authority_pubkey != *authority.key {
-            return Err(ProgramError::InvalidAccountData);
+        Self::settle_escrow(
+            authority,
+            takers_account,
+            fee_taker_account,
+            referrer_account,
+            pdas_temp_token_account,
+            escrow_account,
+            fee_payer_account,
+            token_program,
+            pda_account,
+            |_| fee,
+            program_id,
+        )
+    }
+
+    //inside: impl Processor {}
+    fn process_settle_many(
+        accounts: &[AccountInfo],
+        fee_basis_points: u16,
+        with_referrers: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        msg!(
+            "Process batch settlement with fee: {} basis points",
+            fee_basis_points
+        );
+        if fee_basis_points > MAX_BASIS_POINTS {
+            return Err(FeeOverflow.into());
         }
 
-        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
-            return Err(ProgramError::InvalidAccountData);
+        let account_info_iter = &mut accounts.iter();
+        let authority = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
         }
 
+        let fee_taker_account = next_account_info(account_info_iter)?;
         let fee_payer_account = next_account_info(account_info_iter)?;
         let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
 
-        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        let settlement_len = if with_referrers { 4 } else { 3 };
+        let settlements = account_info_iter.as_slice().chunks_exact(settlement_len);
+        if settlements.len() == 0 || !settlements.remainder().is_empty() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
 
-        let pda_account = next_account_info(account_info_iter)?;
-        if pda != *pda_account.key {
-            return Err(ProgramError::InvalidAccountData);
+        for settlement in settlements {
+            let (escrow_account, pdas_temp_token_account, takers_account) =
+                (&settlement[0], &settlement[1], &settlement[2]);
+            msg!("Settling escrow {}...", escrow_account.key);
+            Self::settle_escrow(
+                authority,
+                takers_account,
+                fee_taker_account,
+                settlement.get(3),
+                pdas_temp_token_account,
+                escrow_account,
+                fee_payer_account,
+                token_program,
+                pda_account,
+                |amount| {
+                    (amount as u128 * fee_basis_points as u128 / MAX_BASIS_POINTS as u128) as u64
+                },
+                program_id,
+            )?;
         }
+        Ok(())
+    }
 
-        if fee > pdas_temp_token_account_info.amount {
+    /// What the payee gets out of the temp token account's tokens once `fee` is taken
+    fn payee_amount(vault_amount: u64, fee: u64) -> Result<u64, ProgramError> {
+        vault_amount.checked_sub(fee).ok_or_else(|| {
             msg!(
                 "Fee too high..., {} should be less than or equal to {}",
                 fee,
-                pdas_temp_token_account_info.amount
+                vault_amount
             );
-            return Err(FeeOverflow.into());
+            FeeOverflow.into()
+        })
+    }
+
+    /// Owner of an account paid out of the temp token account: the account itself for native
+    /// temp accounts, which pay lamports, the token account's owner otherwise
+    fn recipient(
+        account: &AccountInfo,
+        pdas_temp_token_account_info: &TokenAccount,
+    ) -> Result<Pubkey, ProgramError> {
+        Ok(if pdas_temp_token_account_info.is_native() {
+            *account.key
+        } else {
+            TokenAccount::unpack(&account.data.borrow())?.owner
+        })
+    }
+
+    /// Fails when `fee` is above the max fee the payer agreed to
+    fn check_max_fee(escrow_info: &Escrow, fee: u64) -> ProgramResult {
+        if fee > escrow_info.max_fee {
+            msg!(
+                "Fee too high..., {} should be less than or equal to the max fee {}",
+                fee,
+                escrow_info.max_fee
+            );
+            return Err(FeeAboveMaximum.into());
         }
+        Ok(())
+    }
 
-        let amount = pdas_temp_token_account_info.amount - fee;
+    /// Splits `fee` into the fee taker's and the referrer's shares
+    fn split_fee(escrow_info: &Escrow, fee: u64) -> (u64, u64) {
+        let referrer_fee =
+            (fee as u128 * escrow_info.referrer_fee_bps as u128 / MAX_BASIS_POINTS as u128) as u64;
+        (fee - referrer_fee, referrer_fee)
+    }
 
-        if pdas_temp_token_account_info.is_native() {
-            let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
-                token_program.key,
-                pdas_temp_token_account.key,
-                escrow_account.key,
-                &pda,
-                &[&pda],
-            )?;
-            msg!("Calling the token program to close pda's temp account...and add the remaining lamports to the escrow account");
-            invoke_signed(
-                &close_pdas_temp_acc_ix,
-                &[
-                    pdas_temp_token_account.clone(),
-                    escrow_account.clone(),
-                    pda_account.clone(),
-                    token_program.clone(),
-                ],
-                &[&[&b"escrow"[..], &[bump_seed]]],
-            )?;
-            let source_starting_lamports = escrow_account.lamports();
-            **escrow_account.lamports.borrow_mut() = source_starting_lamports
-                .checked_sub(amount)
-                .ok_or(AmountOverflow)?;
-
-            let dest_starting_lamports = takers_account.lamports();
-            **takers_account.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(amount)
-                .ok_or(AmountOverflow)?;
-            if fee > 0 {
-                let source_starting_lamports = escrow_account.lamports();
-                **escrow_account.lamports.borrow_mut() = source_starting_lamports
-                    .checked_sub(fee)
-                    .ok_or(AmountOverflow)?;
+    /// Pays an accepted escrow out to its payee, `fee` computing the fee from the escrowed amount.
+    /// The fee is split between the escrow's fee taker and its referrer, whose account is only
+    /// needed when the escrow has one
+    #[allow(clippy::too_many_arguments)]
+    fn settle_escrow<'a>(
+        authority: &AccountInfo<'a>,
+        takers_account: &AccountInfo<'a>,
+        fee_taker_account: &AccountInfo<'a>,
+        referrer_account: Option<&AccountInfo<'a>>,
+        pdas_temp_token_account: &AccountInfo<'a>,
+        escrow_account: &AccountInfo<'a>,
+        fee_payer_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        fee: impl FnOnce(u64) -> u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
 
-                let dest_starting_lamports = fee_taker_account.lamports();
-                **fee_taker_account.lamports.borrow_mut() = dest_starting_lamports
-                    .checked_add(fee)
-                    .ok_or(AmountOverflow)?;
-            }
-        } else {
-            let transfer_to_taker_ix = spl_token::instruction::transfer(
-                token_program.key,
-                pdas_temp_token_account.key,
-                takers_account.key,
-                &pda,
-                &[&pda],
-                amount,
-            )?;
-            msg!("Calling the token program to transfer tokens to the taker...");
-            let seed = &b"escrow"[..];
-            invoke_signed(
-                &transfer_to_taker_ix,
-                &[
-                    pdas_temp_token_account.clone(),
-                    takers_account.clone(),
-                    pda_account.clone(),
-                    token_program.clone(),
-                ],
-                &[&[seed, &[bump_seed]]],
-            )?;
-            if fee > 0 {
-                let transfer_to_fee_taker_ix = spl_token::instruction::transfer(
-                    token_program.key,
-                    pdas_temp_token_account.key,
-                    fee_taker_account.key,
-                    &pda,
-                    &[&pda],
-                    fee,
-                )?;
-                msg!("Calling the token program to transfer tokens to the fee taker...");
-                invoke_signed(
-                    &transfer_to_fee_taker_ix,
-                    &[
-                        pdas_temp_token_account.clone(),
-                        fee_taker_account.clone(),
-                        pda_account.clone(),
-                        token_program.clone(),
-                    ],
-                    &[&[seed, &[bump_seed]]],
-                )?;
-            }
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::ensure_standard(&escrow_info)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
 
-            let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
-                token_program.key,
-                pdas_temp_token_account.key,
-                fee_payer_account.key,
-                &pda,
-                &[&pda],
-            )?;
-            msg!("Calling the token program to close pda's temp account...");
-            invoke_signed(
-                &close_pdas_temp_acc_ix,
-                &[
-                    pdas_temp_token_account.clone(),
-                    fee_payer_account.clone(),
-                    pda_account.clone(),
-                    token_program.clone(),
-                ],
-                &[&[&b"escrow"[..], &[bump_seed]]],
-            )?;
+        Self::transition(&mut escrow_info, EscrowStatus::Settled)?;
+        if escrow_info.authority_pubkey != *authority.key {
+            return Err(InvalidAuthorityId.into());
+        }
+
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+        if escrow_info.rent_payer_pubkey != *fee_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+
+        if escrow_info.payee_pubkey
+            != Self::recipient(takers_account, &pdas_temp_token_account_info)?
+        {
+            return Err(PayeeMismatch.into());
+        }
+        if escrow_info.fee_taker_pubkey
+            != Self::recipient(fee_taker_account, &pdas_temp_token_account_info)?
+        {
+            return Err(FeeTakerMismatch.into());
+        }
+
+        let fee = fee(pdas_temp_token_account_info.amount);
+        Self::check_max_fee(&escrow_info, fee)?;
+        Self::payee_amount(pdas_temp_token_account_info.amount, fee)?;
+        let (taker_fee, referrer_fee) = Self::split_fee(&escrow_info, fee);
+
+        let mut fees = vec![(fee_taker_account, taker_fee)];
+        if escrow_info.referrer_pubkey != Pubkey::default() {
+            let referrer_account = referrer_account.ok_or(ReferrerMismatch)?;
+            if escrow_info.referrer_pubkey
+                != Self::recipient(referrer_account, &pdas_temp_token_account_info)?
+            {
+                return Err(ReferrerMismatch.into());
+            }
+            fees.push((referrer_account, referrer_fee));
         }
 
+        Self::release_vault(
+            token_program,
+            pdas_temp_token_account,
+            &pdas_temp_token_account_info,
+            escrow_account,
+            takers_account,
+            &fees,
+            fee_payer_account,
+            pda_account,
+            bump_seed,
+        )?;
+
         msg!("Mark the escrow account as settled...");
-        escrow_info.is_settled = true;
         escrow_info.fee = fee;
-        escrow_info.payee_pubkey = *takers_account.key;
-        escrow_info.fee_taker_pubkey = *fee_taker_account.key;
         Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
         Ok(())
     }
@@ -300,25 +879,1138 @@ impl Processor {
         let escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
 
         if escrow_info.authority_pubkey != *authority.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(InvalidAuthorityId.into());
         }
 
         if escrow_account.owner != program_id {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(InvalidEscrowOwner.into());
         }
 
-        if !escrow_info.is_settled() {
+        if !escrow_info.status.is_final() {
             return Err(AccountNotSettled.into());
         }
 
         let fee_payer_account = next_account_info(account_info_iter)?;
-        msg!("Closing the escrow account...");
-        **fee_payer_account.lamports.borrow_mut() = fee_payer_account
-            .lamports()
-            .checked_add(escrow_account.lamports())
-            .ok_or(AmountOverflow)?;
-        **escrow_account.lamports.borrow_mut() = 0;
-        *escrow_account.data.borrow_mut() = &mut [];
+        if escrow_info.rent_payer_pubkey != *fee_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+
+        Self::close_escrow(escrow_account, fee_payer_account)
+    }
+
+    //inside: impl Processor {}
+    fn process_update_authority(
+        accounts: &[AccountInfo],
+        new_authority: &Pubkey,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        if escrow_info.authority_pubkey != *authority.key {
+            return Err(InvalidAuthorityId.into());
+        }
+
+        if let Ok(new_authority_account) = next_account_info(account_info_iter) {
+            if new_authority_account.key != new_authority {
+                return Err(NewAuthorityMismatch.into());
+            }
+            if !new_authority_account.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+        }
+
+        msg!("Rotating the escrow authority to {}", new_authority);
+        escrow_info.authority_pubkey = *new_authority;
+        // the new authority is not known to be a PDA of the previous authority's program
+        escrow_info.authority_program_id = Pubkey::default();
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_accept(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payee = next_account_info(account_info_iter)?;
+
+        if !payee.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let authority = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        // a hash locked escrow is tied to the payee the payer chose, the authority has no say in it
+        if let EscrowMode::HashLock {
+            payee: locked_payee,
+            ..
+        } = escrow_info.mode
+        {
+            if locked_payee != *payee.key {
+                return Err(PayeeMismatch.into());
+            }
+        } else {
+            if !authority.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if escrow_info.authority_pubkey != *authority.key {
+                return Err(InvalidAuthorityId.into());
+            }
+        }
+        // a swap is only ever taken through Exchange, paying the payer in the same instruction
+        if let EscrowMode::Swap { .. } = escrow_info.mode {
+            return Err(InvalidEscrowMode.into());
+        }
+        Self::transition(&mut escrow_info, EscrowStatus::Active)?;
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+        msg!("Mark the escrow account as accepted by {}...", payee.key);
+        escrow_info.payee_pubkey = *payee.key;
+        escrow_info.accepted_at = clock.unix_timestamp;
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_cancel(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payer_account = next_account_info(account_info_iter)?;
+
+        if !payer_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        if escrow_info.payer_pubkey != *payer_account.key {
+            return Err(PayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+        // the payee of a hash lock relies on it staying funded until it expires
+        if let EscrowMode::HashLock { .. } = escrow_info.mode {
+            return Err(InvalidEscrowMode.into());
+        }
+        Self::transition(&mut escrow_info, EscrowStatus::Cancelled)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        msg!("Calling the token program to transfer token account ownership back to the payer...");
+        Self::return_vault(
+            token_program,
+            pdas_temp_token_account,
+            payer_account,
+            pda_account,
+            bump_seed,
+        )?;
+
+        let rent_payer_account = next_account_info(account_info_iter)?;
+        if escrow_info.rent_payer_pubkey != *rent_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+
+        Self::close_escrow(escrow_account, rent_payer_account)
+    }
+
+    //inside: impl Processor {}
+    fn process_crank(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let payees_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
+        let fee_payer_account = next_account_info(account_info_iter)?;
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::ensure_active(&escrow_info)?;
+        if escrow_info.authority_pubkey != *authority.key {
+            return Err(InvalidAuthorityId.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+        if escrow_info.payee_pubkey != TokenAccount::unpack(&payees_account.data.borrow())?.owner {
+            return Err(PayeeMismatch.into());
+        }
+        if escrow_info.rent_payer_pubkey != *fee_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+
+        let (period, amount_per_period, released_periods) = match escrow_info.mode {
+            EscrowMode::Recurring {
+                period,
+                amount_per_period,
+                released_periods,
+            } => (period, amount_per_period, released_periods),
+            _ => return Err(InvalidEscrowMode.into()),
+        };
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let released_periods = released_periods.checked_add(1).ok_or(AmountOverflow)?;
+        let due_at = period
+            .checked_mul(released_periods as i64)
+            .and_then(|elapsed| escrow_info.accepted_at.checked_add(elapsed))
+            .ok_or(AmountOverflow)?;
+        if clock.unix_timestamp < due_at {
+            msg!(
+                "Next release is due at {}, now is {}",
+                due_at,
+                clock.unix_timestamp
+            );
+            return Err(ReleaseNotDue.into());
+        }
+
+        let amount = amount_per_period.min(pdas_temp_token_account_info.amount);
+        msg!(
+            "Calling the token program to release {} tokens to the payee...",
+            amount
+        );
+        Self::transfer_from_vault(
+            token_program,
+            pdas_temp_token_account,
+            payees_account,
+            pda_account,
+            bump_seed,
+            amount,
+        )?;
+
+        if amount == pdas_temp_token_account_info.amount {
+            msg!("Calling the token program to close pda's temp account...");
+            Self::close_vault(
+                token_program,
+                pdas_temp_token_account,
+                fee_payer_account,
+                pda_account,
+                bump_seed,
+            )?;
+            Self::transition(&mut escrow_info, EscrowStatus::Settled)?;
+        }
+
+        escrow_info.mode = EscrowMode::Recurring {
+            period,
+            amount_per_period,
+            released_periods,
+        };
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_withdraw(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payee = next_account_info(account_info_iter)?;
+
+        if !payee.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let payees_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
+        let rent_payer_account = next_account_info(account_info_iter)?;
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::ensure_active(&escrow_info)?;
+        if escrow_info.payee_pubkey != *payee.key {
+            return Err(PayeeMismatch.into());
+        }
+        if escrow_info.rent_payer_pubkey != *rent_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let vested = escrow_info
+            .vested_amount(clock.unix_timestamp)
+            .ok_or(InvalidEscrowMode)?;
+        let (start_at, cliff_at, end_at, released) = match escrow_info.mode {
+            EscrowMode::Vesting {
+                start_at,
+                cliff_at,
+                end_at,
+                released,
+            } => (start_at, cliff_at, end_at, released),
+            _ => return Err(InvalidEscrowMode.into()),
+        };
+
+        let amount = vested.saturating_sub(released);
+        if amount == 0 {
+            msg!("Nothing vested to withdraw at {}", clock.unix_timestamp);
+            return Err(ReleaseNotDue.into());
+        }
+
+        msg!(
+            "Calling the token program to release {} tokens to the payee...",
+            amount
+        );
+        Self::transfer_from_vault(
+            token_program,
+            pdas_temp_token_account,
+            payees_account,
+            pda_account,
+            bump_seed,
+            amount,
+        )?;
+
+        if amount == pdas_temp_token_account_info.amount {
+            msg!("Calling the token program to close pda's temp account...");
+            Self::close_vault(
+                token_program,
+                pdas_temp_token_account,
+                rent_payer_account,
+                pda_account,
+                bump_seed,
+            )?;
+            Self::transition(&mut escrow_info, EscrowStatus::Settled)?;
+        }
+
+        escrow_info.mode = EscrowMode::Vesting {
+            start_at,
+            cliff_at,
+            end_at,
+            released: vested,
+        };
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_revoke(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let payers_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
+        let rent_payer_account = next_account_info(account_info_iter)?;
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::ensure_active(&escrow_info)?;
+        if escrow_info.authority_pubkey != *authority.key {
+            return Err(InvalidAuthorityId.into());
+        }
+        if escrow_info.rent_payer_pubkey != *rent_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+        if escrow_info.payer_pubkey
+            != TokenAccount::unpack(&payers_token_account.data.borrow())?.owner
+        {
+            return Err(PayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        let vested = escrow_info
+            .vested_amount(clock.unix_timestamp)
+            .ok_or(InvalidEscrowMode)?;
+        let (start_at, cliff_at, end_at, released) = match escrow_info.mode {
+            EscrowMode::Vesting {
+                start_at,
+                cliff_at,
+                end_at,
+                released,
+            } => (start_at, cliff_at, end_at, released),
+            _ => return Err(InvalidEscrowMode.into()),
+        };
+
+        let unvested = pdas_temp_token_account_info
+            .amount
+            .saturating_sub(vested.saturating_sub(released));
+        if unvested > 0 {
+            msg!(
+                "Calling the token program to send {} unvested tokens back to the payer...",
+                unvested
+            );
+            Self::transfer_from_vault(
+                token_program,
+                pdas_temp_token_account,
+                payers_token_account,
+                pda_account,
+                bump_seed,
+                unvested,
+            )?;
+        }
+
+        if unvested == pdas_temp_token_account_info.amount {
+            msg!("Calling the token program to close pda's temp account...");
+            Self::close_vault(
+                token_program,
+                pdas_temp_token_account,
+                rent_payer_account,
+                pda_account,
+                bump_seed,
+            )?;
+            Self::transition(&mut escrow_info, EscrowStatus::Refunded)?;
+        }
+
+        msg!(
+            "Freezing the vesting schedule at {} vested tokens...",
+            vested
+        );
+        escrow_info.amount = vested;
+        escrow_info.mode = EscrowMode::Vesting {
+            start_at,
+            cliff_at,
+            end_at: end_at.min(clock.unix_timestamp),
+            released,
+        };
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_attest(
+        accounts: &[AccountInfo],
+        payload: [u8; 32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let attestor = next_account_info(account_info_iter)?;
+
+        if !attestor.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let attestation_account = next_account_info(account_info_iter)?;
+        if attestation_account.owner != program_id {
+            return Err(InvalidAttestationOwner.into());
+        }
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        if !rent.is_exempt(
+            attestation_account.lamports(),
+            attestation_account.data_len(),
+        ) {
+            return Err(NotRentExempt.into());
+        }
+
+        let mut attestation_info =
+            Attestation::unpack_unchecked(&attestation_account.data.borrow())?;
+        if attestation_info.is_initialized {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+        msg!(
+            "Recording the attestation of {} for {}...",
+            attestor.key,
+            escrow_account.key
+        );
+        attestation_info.is_initialized = true;
+        attestation_info.attestor_pubkey = *attestor.key;
+        attestation_info.escrow_pubkey = *escrow_account.key;
+        attestation_info.payload = payload;
+        attestation_info.attested_at = clock.unix_timestamp;
+        Attestation::pack(attestation_info, &mut attestation_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_settle_by_condition(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let takers_account = next_account_info(account_info_iter)?;
+
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
+        let rent_payer_account = next_account_info(account_info_iter)?;
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::transition(&mut escrow_info, EscrowStatus::Settled)?;
+        if escrow_info.rent_payer_pubkey != *rent_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+
+        let takers_account_owner = if pdas_temp_token_account_info.is_native() {
+            *takers_account.key
+        } else {
+            TokenAccount::unpack(&takers_account.data.borrow())?.owner
+        };
+        if escrow_info.payee_pubkey != takers_account_owner {
+            return Err(PayeeMismatch.into());
+        }
+
+        let (attestor, condition) = match escrow_info.mode {
+            EscrowMode::Conditional {
+                attestor,
+                condition,
+            } => (attestor, condition),
+            _ => return Err(InvalidEscrowMode.into()),
+        };
+
+        let attestation_account = next_account_info(account_info_iter)?;
+        if attestation_account.owner != program_id {
+            return Err(InvalidAttestationOwner.into());
+        }
+        let attestation_info = Attestation::unpack(&attestation_account.data.borrow())?;
+        if attestation_info.attestor_pubkey != attestor
+            || attestation_info.escrow_pubkey != *escrow_account.key
+            || attestation_info.payload != condition
+        {
+            msg!("Attestation does not satisfy the escrow's condition");
+            return Err(ConditionNotMet.into());
+        }
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        Self::release_vault(
+            token_program,
+            pdas_temp_token_account,
+            &pdas_temp_token_account_info,
+            escrow_account,
+            takers_account,
+            &[],
+            rent_payer_account,
+            pda_account,
+            bump_seed,
+        )?;
+
+        msg!("Mark the escrow account as settled...");
+        escrow_info.fee = 0;
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_claim(
+        accounts: &[AccountInfo],
+        preimage: &[u8; 32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payee = next_account_info(account_info_iter)?;
+
+        if !payee.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let takers_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
+        let rent_payer_account = next_account_info(account_info_iter)?;
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        let (hash_lock, expires_at, locked_payee) = match escrow_info.mode {
+            EscrowMode::HashLock {
+                hash,
+                expires_at,
+                payee,
+            } => (hash, expires_at, payee),
+            _ => return Err(InvalidEscrowMode.into()),
+        };
+        if locked_payee != *payee.key {
+            return Err(PayeeMismatch.into());
+        }
+        let accepting = escrow_info.status == EscrowStatus::Pending;
+        if accepting {
+            Self::transition(&mut escrow_info, EscrowStatus::Active)?;
+            escrow_info.payee_pubkey = *payee.key;
+        }
+        Self::transition(&mut escrow_info, EscrowStatus::Settled)?;
+        if escrow_info.rent_payer_pubkey != *rent_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+
+        let takers_account_owner = if pdas_temp_token_account_info.is_native() {
+            *takers_account.key
+        } else {
+            TokenAccount::unpack(&takers_account.data.borrow())?.owner
+        };
+        if escrow_info.payee_pubkey != takers_account_owner {
+            return Err(PayeeMismatch.into());
+        }
+
+        if hash(preimage).to_bytes() != hash_lock {
+            return Err(InvalidPreimage.into());
+        }
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        if clock.unix_timestamp >= expires_at {
+            msg!("Hash lock expired at {}", expires_at);
+            return Err(EscrowExpired.into());
+        }
+        if accepting {
+            escrow_info.accepted_at = clock.unix_timestamp;
+        }
+
+        Self::release_vault(
+            token_program,
+            pdas_temp_token_account,
+            &pdas_temp_token_account_info,
+            escrow_account,
+            takers_account,
+            &[],
+            rent_payer_account,
+            pda_account,
+            bump_seed,
+        )?;
+
+        msg!("Mark the escrow account as settled...");
+        escrow_info.fee = 0;
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_refund(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payer_account = next_account_info(account_info_iter)?;
+
+        if !payer_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::transition(&mut escrow_info, EscrowStatus::Refunded)?;
+        if escrow_info.payer_pubkey != *payer_account.key {
+            return Err(PayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+
+        let expires_at = match escrow_info.mode {
+            EscrowMode::HashLock { expires_at, .. } => expires_at,
+            _ => return Err(InvalidEscrowMode.into()),
+        };
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        if clock.unix_timestamp < expires_at {
+            msg!(
+                "Hash lock expires at {}, now is {}",
+                expires_at,
+                clock.unix_timestamp
+            );
+            return Err(HashLockNotExpired.into());
+        }
+
+        msg!("Calling the token program to transfer token account ownership back to the payer...");
+        Self::return_vault(
+            token_program,
+            pdas_temp_token_account,
+            payer_account,
+            pda_account,
+            bump_seed,
+        )?;
+
+        msg!("Mark the escrow account as refunded...");
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_exchange(
+        accounts: &[AccountInfo],
+        amount: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let taker = next_account_info(account_info_iter)?;
+
+        if !taker.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let takers_sending_token_account = next_account_info(account_info_iter)?;
+        let takers_account = next_account_info(account_info_iter)?;
+        let payers_receiving_token_account = next_account_info(account_info_iter)?;
+
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
+        if pdas_temp_token_account_info.amount != amount {
+            msg!(
+                "Got Mismatched amount..., got: {} , expected {}",
+                amount,
+                pdas_temp_token_account_info.amount
+            );
+            return Err(ExpectedAmountMismatch.into());
+        }
+
+        let rent_payer_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::transition(&mut escrow_info, EscrowStatus::Active)?;
+        Self::transition(&mut escrow_info, EscrowStatus::Settled)?;
+        if escrow_info.rent_payer_pubkey != *rent_payer_account.key {
+            return Err(RentPayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+
+        let expected_amount = match escrow_info.mode {
+            EscrowMode::Swap {
+                receive_account,
+                expected_amount,
+            } => {
+                if receive_account != *payers_receiving_token_account.key {
+                    return Err(ReceiveAccountMismatch.into());
+                }
+                expected_amount
+            }
+            _ => return Err(InvalidEscrowMode.into()),
+        };
+
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let bump_seed = Self::check_pda(&escrow_info, pda_account, program_id)?;
+
+        let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+        let transfer_to_payer_ix = spl_token::instruction::transfer(
+            token_program.key,
+            takers_sending_token_account.key,
+            payers_receiving_token_account.key,
+            taker.key,
+            &[taker.key],
+            expected_amount,
+        )?;
+        msg!("Calling the token program to transfer tokens to the payer...");
+        invoke(
+            &transfer_to_payer_ix,
+            &[
+                takers_sending_token_account.clone(),
+                payers_receiving_token_account.clone(),
+                taker.clone(),
+                token_program.clone(),
+            ],
+        )?;
+
+        Self::release_vault(
+            token_program,
+            pdas_temp_token_account,
+            &pdas_temp_token_account_info,
+            escrow_account,
+            takers_account,
+            &[],
+            rent_payer_account,
+            pda_account,
+            bump_seed,
+        )?;
+
+        msg!("Mark the escrow account as swapped with {}...", taker.key);
+        escrow_info.payee_pubkey = *taker.key;
+        escrow_info.accepted_at = clock.unix_timestamp;
+        escrow_info.fee = 0;
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_deposit(
+        accounts: &[AccountInfo],
+        amount: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payer_account = next_account_info(account_info_iter)?;
+
+        if !payer_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let payers_token_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        match escrow_info.status {
+            EscrowStatus::Pending | EscrowStatus::Active => {}
+            status => return Err(Self::status_error(status)),
+        }
+        if escrow_info.payer_pubkey != *payer_account.key {
+            return Err(PayerMismatch.into());
+        }
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+
+        let token_program = next_account_info(account_info_iter)?;
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let vault_amount = TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?.amount;
+        let transfer_to_vault_ix = spl_token::instruction::transfer(
+            token_program.key,
+            payers_token_account.key,
+            pdas_temp_token_account.key,
+            payer_account.key,
+            &[payer_account.key],
+            amount,
+        )?;
+        msg!(
+            "Calling the token program to transfer {} more tokens to pda's temp account...",
+            amount
+        );
+        invoke(
+            &transfer_to_vault_ix,
+            &[
+                payers_token_account.clone(),
+                pdas_temp_token_account.clone(),
+                payer_account.clone(),
+                token_program.clone(),
+            ],
+        )?;
+
+        // counted from the vault itself rather than trusted from the instruction
+        let deposited = TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?
+            .amount
+            .checked_sub(vault_amount)
+            .ok_or(AmountOverflow)?;
+        escrow_info.amount = escrow_info
+            .amount
+            .checked_add(deposited)
+            .ok_or(AmountOverflow)?;
+        Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_quote(accounts: &[AccountInfo], fee: u64, program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(InvalidEscrowOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+        Self::ensure_standard(&escrow_info)?;
+        Self::transition(&mut escrow_info, EscrowStatus::Settled)?;
+
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        if escrow_info.payer_temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(VaultMismatch.into());
+        }
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.data.borrow())?;
+
+        // native temp accounts are closed into the escrow account, their rent staying there until close
+        let vault_tokens_in_lamports = if pdas_temp_token_account_info.is_native() {
+            pdas_temp_token_account_info.amount
+        } else {
+            0
+        };
+        Self::check_max_fee(&escrow_info, fee)?;
+        let payee_amount = Self::payee_amount(pdas_temp_token_account_info.amount, fee)?;
+        let (taker_fee, referrer_fee) = Self::split_fee(&escrow_info, fee);
+        let quote = SettlementQuote {
+            payee_amount,
+            fee: taker_fee,
+            vault_rent: pdas_temp_token_account
+                .lamports()
+                .saturating_sub(vault_tokens_in_lamports),
+            escrow_rent: escrow_account.lamports(),
+            referrer_fee,
+        };
+
+        let mut data = [0; SettlementQuote::LEN];
+        quote.pack_into_slice(&mut data);
+        set_return_data(&data);
+        Ok(())
+    }
+
+    /// Reads the program's config, checking the account is the one at its address
+    fn load_config(
+        config_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<Config, ProgramError> {
+        if *config_account.key != find_config_address(program_id).0
+            || config_account.owner != program_id
+        {
+            return Err(InvalidConfig.into());
+        }
+        Config::unpack(&config_account.data.borrow())
+    }
+
+    /// Reads the program's governance, checking the account is the one at its address
+    fn load_governance(
+        governance_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<Governance, ProgramError> {
+        if *governance_account.key != find_governance_address(program_id).0
+            || governance_account.owner != program_id
+        {
+            return Err(InvalidGovernance.into());
+        }
+        Governance::unpack(&governance_account.data.borrow())
+    }
+
+    //inside: impl Processor {}
+    fn process_init_config(
+        accounts: &[AccountInfo],
+        params: ConfigParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let upgrade_authority = next_account_info(account_info_iter)?;
+        if !upgrade_authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let program_data_account = next_account_info(account_info_iter)?;
+        let (program_data_address, _) =
+            Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+        if program_data_address != *program_data_account.key {
+            return Err(InvalidUpgradeAuthority.into());
+        }
+        match limited_deserialize(
+            &program_data_account.data.borrow(),
+            UpgradeableLoaderState::size_of_programdata_metadata() as u64,
+        ) {
+            Ok(UpgradeableLoaderState::ProgramData {
+                upgrade_authority_address: Some(authority),
+                ..
+            }) if authority == *upgrade_authority.key => {}
+            _ => return Err(InvalidUpgradeAuthority.into()),
+        }
+
+        let config_account = next_account_info(account_info_iter)?;
+        let governance_account = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        if !params.is_valid() {
+            return Err(InvalidConfigParams.into());
+        }
+        let (config_address, config_bump_seed) = find_config_address(program_id);
+        if config_address != *config_account.key {
+            return Err(InvalidConfig.into());
+        }
+        let (governance_address, governance_bump_seed) = find_governance_address(program_id);
+        if governance_address != *governance_account.key {
+            return Err(InvalidGovernance.into());
+        }
+
+        msg!("Creating the config and governance accounts...");
+        Self::create_pda_account(
+            config_account,
+            upgrade_authority,
+            system_program,
+            rent,
+            Config::LEN,
+            &[b"config", &[config_bump_seed]],
+            program_id,
+        )?;
+        Self::create_pda_account(
+            governance_account,
+            upgrade_authority,
+            system_program,
+            rent,
+            Governance::LEN,
+            &[b"governance", &[governance_bump_seed]],
+            program_id,
+        )?;
+
+        Config::pack(
+            Config {
+                is_initialized: true,
+                params,
+            },
+            &mut config_account.data.borrow_mut(),
+        )?;
+        Governance::pack(
+            Governance {
+                is_initialized: true,
+                pending: None,
+                eta: 0,
+            },
+            &mut governance_account.data.borrow_mut(),
+        )?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_propose_config_change(
+        accounts: &[AccountInfo],
+        params: ConfigParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let admin = next_account_info(account_info_iter)?;
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let config = Self::load_config(next_account_info(account_info_iter)?, program_id)?;
+        if config.params.admin != *admin.key {
+            return Err(AdminMismatch.into());
+        }
+        let governance_account = next_account_info(account_info_iter)?;
+        let mut governance = Self::load_governance(governance_account, program_id)?;
+        let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+        if !params.is_valid() {
+            return Err(InvalidConfigParams.into());
+        }
+
+        // the current delay applies, a change shortening it only counts for the next ones
+        governance.eta = clock
+            .unix_timestamp
+            .checked_add(config.params.delay)
+            .ok_or(InvalidConfigParams)?;
+        msg!("Config change executable at {}", governance.eta);
+        governance.pending = Some(params);
+        Governance::pack(governance, &mut governance_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_execute_config_change(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let config_account = next_account_info(account_info_iter)?;
+        let mut config = Self::load_config(config_account, program_id)?;
+        let governance_account = next_account_info(account_info_iter)?;
+        let mut governance = Self::load_governance(governance_account, program_id)?;
+        let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+        if clock.unix_timestamp < governance.eta {
+            msg!(
+                "Config change not due..., executable at {}, now {}",
+                governance.eta,
+                clock.unix_timestamp
+            );
+            return Err(ConfigChangeNotDue.into());
+        }
+        config.params = governance.pending.take().ok_or(NoPendingConfigChange)?;
+
+        Config::pack(config, &mut config_account.data.borrow_mut())?;
+        Governance::pack(governance, &mut governance_account.data.borrow_mut())?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_cancel_config_change(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let admin = next_account_info(account_info_iter)?;
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let config = Self::load_config(next_account_info(account_info_iter)?, program_id)?;
+        if config.params.admin != *admin.key {
+            return Err(AdminMismatch.into());
+        }
+        let governance_account = next_account_info(account_info_iter)?;
+        let mut governance = Self::load_governance(governance_account, program_id)?;
+
+        governance.pending.take().ok_or(NoPendingConfigChange)?;
+        Governance::pack(governance, &mut governance_account.data.borrow_mut())?;
         Ok(())
     }
 }
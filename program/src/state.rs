@@ -1,12 +1,224 @@
 use solana_program::{
+    clock::UnixTimestamp,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
 
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use crate::error::EscrowError::EscrowClosed;
+
+/// Denominator of fees expressed in basis points
+pub const MAX_BASIS_POINTS: u16 = 10_000;
+
+/// Lifecycle of an escrow, see `Processor::transition` for the allowed moves
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscrowStatus {
+    Uninitialized,
+    /// Funded, waiting to be accepted. The payer can still cancel
+    Pending,
+    /// Accepted, waiting to be settled
+    Active,
+    Disputed,
+    Settled,
+    Refunded,
+    Cancelled,
+    Expired,
+    /// Written over the zeroed data of a closed escrow account so it can never be unpacked again
+    Closed,
+}
+
+impl EscrowStatus {
+    /// Whether the escrow has reached an end state and its account can be closed
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            EscrowStatus::Settled
+                | EscrowStatus::Refunded
+                | EscrowStatus::Cancelled
+                | EscrowStatus::Expired
+        )
+    }
+
+    fn from_u8(status: u8) -> Result<Self, ProgramError> {
+        Ok(match status {
+            0 => EscrowStatus::Uninitialized,
+            1 => EscrowStatus::Pending,
+            2 => EscrowStatus::Active,
+            3 => EscrowStatus::Disputed,
+            4 => EscrowStatus::Settled,
+            5 => EscrowStatus::Refunded,
+            6 => EscrowStatus::Cancelled,
+            7 => EscrowStatus::Expired,
+            8 => EscrowStatus::Closed,
+            _ => return Err(ProgramError::InvalidAccountData),
+        })
+    }
+}
+
+/// How the escrowed tokens are released to the payee
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscrowMode {
+    /// Released all at once when the authority settles
+    Standard,
+    /// Released one period at a time, starting one period after acceptance
+    Recurring {
+        /// Seconds between two releases
+        period: UnixTimestamp,
+        /// Amount released every period, the last release gets what is left
+        amount_per_period: u64,
+        /// Number of periods already released
+        released_periods: u64,
+    },
+    /// Released linearly between `start_at` and `end_at`, nothing before `cliff_at`
+    Vesting {
+        start_at: UnixTimestamp,
+        cliff_at: UnixTimestamp,
+        end_at: UnixTimestamp,
+        /// Amount already withdrawn by the payee
+        released: u64,
+    },
+    /// Released to the payee by anyone once `attestor` attests `condition` for the escrow
+    Conditional {
+        attestor: Pubkey,
+        condition: [u8; 32],
+    },
+    /// Claimed by the payee with the SHA-256 preimage of `hash` before `expires_at`,
+    /// refundable to the payer afterwards
+    HashLock {
+        hash: [u8; 32],
+        expires_at: UnixTimestamp,
+        /// The only key that can accept and claim the escrow, set by the payer at init
+        payee: Pubkey,
+    },
+    /// Taken by whoever sends `expected_amount` of another token to `receive_account` in exchange
+    Swap {
+        /// The payer's token account receiving the other side of the trade
+        receive_account: Pubkey,
+        expected_amount: u64,
+    },
+}
+
+impl EscrowMode {
+    const LEN: usize = 97;
+
+    fn unpack_from_slice(src: &[u8; EscrowMode::LEN]) -> Result<Self, ProgramError> {
+        let (tag, params) = array_refs![src, 1, 96];
+        Ok(match tag[0] {
+            0 => EscrowMode::Standard,
+            1 => {
+                let (period, amount_per_period, released_periods, _) =
+                    array_refs![params, 8, 8, 8, 72];
+                EscrowMode::Recurring {
+                    period: i64::from_le_bytes(*period),
+                    amount_per_period: u64::from_le_bytes(*amount_per_period),
+                    released_periods: u64::from_le_bytes(*released_periods),
+                }
+            }
+            2 => {
+                let (start_at, cliff_at, end_at, released, _) = array_refs![params, 8, 8, 8, 8, 64];
+                EscrowMode::Vesting {
+                    start_at: i64::from_le_bytes(*start_at),
+                    cliff_at: i64::from_le_bytes(*cliff_at),
+                    end_at: i64::from_le_bytes(*end_at),
+                    released: u64::from_le_bytes(*released),
+                }
+            }
+            3 => {
+                let (attestor, condition, _) = array_refs![params, 32, 32, 32];
+                EscrowMode::Conditional {
+                    attestor: Pubkey::new_from_array(*attestor),
+                    condition: *condition,
+                }
+            }
+            4 => {
+                let (hash, expires_at, payee, _) = array_refs![params, 32, 8, 32, 24];
+                EscrowMode::HashLock {
+                    hash: *hash,
+                    expires_at: i64::from_le_bytes(*expires_at),
+                    payee: Pubkey::new_from_array(*payee),
+                }
+            }
+            5 => {
+                let (receive_account, expected_amount, _) = array_refs![params, 32, 8, 56];
+                EscrowMode::Swap {
+                    receive_account: Pubkey::new_from_array(*receive_account),
+                    expected_amount: u64::from_le_bytes(*expected_amount),
+                }
+            }
+            _ => return Err(ProgramError::InvalidAccountData),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8; EscrowMode::LEN]) {
+        let (tag_dst, params_dst) = mut_array_refs![dst, 1, 96];
+        *params_dst = [0; 96];
+        match self {
+            EscrowMode::Standard => tag_dst[0] = 0,
+            EscrowMode::Recurring {
+                period,
+                amount_per_period,
+                released_periods,
+            } => {
+                tag_dst[0] = 1;
+                let (period_dst, amount_per_period_dst, released_periods_dst, _) =
+                    mut_array_refs![params_dst, 8, 8, 8, 72];
+                *period_dst = period.to_le_bytes();
+                *amount_per_period_dst = amount_per_period.to_le_bytes();
+                *released_periods_dst = released_periods.to_le_bytes();
+            }
+            EscrowMode::Vesting {
+                start_at,
+                cliff_at,
+                end_at,
+                released,
+            } => {
+                tag_dst[0] = 2;
+                let (start_at_dst, cliff_at_dst, end_at_dst, released_dst, _) =
+                    mut_array_refs![params_dst, 8, 8, 8, 8, 64];
+                *start_at_dst = start_at.to_le_bytes();
+                *cliff_at_dst = cliff_at.to_le_bytes();
+                *end_at_dst = end_at.to_le_bytes();
+                *released_dst = released.to_le_bytes();
+            }
+            EscrowMode::Conditional {
+                attestor,
+                condition,
+            } => {
+                tag_dst[0] = 3;
+                let (attestor_dst, condition_dst, _) = mut_array_refs![params_dst, 32, 32, 32];
+                attestor_dst.copy_from_slice(attestor.as_ref());
+                *condition_dst = *condition;
+            }
+            EscrowMode::HashLock {
+                hash,
+                expires_at,
+                payee,
+            } => {
+                tag_dst[0] = 4;
+                let (hash_dst, expires_at_dst, payee_dst, _) =
+                    mut_array_refs![params_dst, 32, 8, 32, 24];
+                *hash_dst = *hash;
+                *expires_at_dst = expires_at.to_le_bytes();
+                payee_dst.copy_from_slice(payee.as_ref());
+            }
+            EscrowMode::Swap {
+                receive_account,
+                expected_amount,
+            } => {
+                tag_dst[0] = 5;
+                let (receive_account_dst, expected_amount_dst, _) =
+                    mut_array_refs![params_dst, 32, 8, 56];
+                receive_account_dst.copy_from_slice(receive_account.as_ref());
+                *expected_amount_dst = expected_amount.to_le_bytes();
+            }
+        }
+    }
+}
+
 pub struct Escrow {
-    pub is_initialized: bool,
-    pub is_settled: bool,
+    pub status: EscrowStatus,
     pub payer_pubkey: Pubkey,
     pub payee_pubkey: Pubkey,
     pub payer_temp_token_account_pubkey: Pubkey,
@@ -14,23 +226,60 @@ pub struct Escrow {
     pub fee_taker_pubkey: Pubkey,
     pub amount: u64,
     pub fee: u64,
+    /// When the payee accepted the escrow, zero while pending
+    pub accepted_at: UnixTimestamp,
+    /// Who funded the escrow account, the only key its rent is returned to
+    pub rent_payer_pubkey: Pubkey,
+    /// Canonical bump of the PDA owning the temp token account, found once at init
+    pub bump_seed: u8,
+    /// External order reference, the nonce the escrow address is derived from with the payer and authority
+    pub reference: [u8; 32],
+    /// Partner paid a share of the fee on settlement, the default pubkey when there is none
+    pub referrer_pubkey: Pubkey,
+    /// Share of the fee paid to the referrer in basis points, the fee taker getting the rest
+    pub referrer_fee_bps: u16,
+    /// Highest fee the payer agreed to at init, settling with a higher one fails
+    pub max_fee: u64,
+    /// Program whose PDA the authority is, checked against its seeds at init, the default pubkey
+    /// when the authority is not delegated to a program
+    pub authority_program_id: Pubkey,
+    pub mode: EscrowMode,
 }
 
 impl Escrow {
-    pub fn is_settled(&self) -> bool {
-        self.is_settled
+    /// Program the authority is delegated to, `None` when it is not a verified PDA
+    pub fn authority_program(&self) -> Option<&Pubkey> {
+        Some(&self.authority_program_id).filter(|program_id| **program_id != Pubkey::default())
     }
-}
 
-use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+    /// Amount of a vesting escrow unlocked at `now`, `None` for other modes
+    pub fn vested_amount(&self, now: UnixTimestamp) -> Option<u64> {
+        let (start_at, cliff_at, end_at) = match self.mode {
+            EscrowMode::Vesting {
+                start_at,
+                cliff_at,
+                end_at,
+                ..
+            } => (start_at, cliff_at, end_at),
+            _ => return None,
+        };
+
+        Some(if now >= end_at {
+            self.amount
+        } else if now < cliff_at || now <= start_at {
+            0
+        } else {
+            (self.amount as u128 * (now - start_at) as u128 / (end_at - start_at) as u128) as u64
+        })
+    }
+}
 
 impl Pack for Escrow {
-    const LEN: usize = 178;
+    const LEN: usize = 421;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, Escrow::LEN];
         let (
-            is_initialized,
-            is_settled,
+            status,
             payer_pubkey,
             payee_pubkey,
             payer_temp_token_account_pubkey,
@@ -38,20 +287,22 @@ impl Pack for Escrow {
             fee_taker_pubkey,
             amount,
             fee,
-        ) = array_refs![src, 1, 1, 32, 32, 32, 32, 32, 8, 8];
-        let is_initialized = match is_initialized {
-            [0] => false,
-            [1] => true,
-            _ => return Err(ProgramError::InvalidAccountData),
-        };
-        let is_settled = match is_settled {
-            [0] => false,
-            [1] => true,
-            _ => return Err(ProgramError::InvalidAccountData),
-        };
+            accepted_at,
+            rent_payer_pubkey,
+            bump_seed,
+            reference,
+            referrer_pubkey,
+            referrer_fee_bps,
+            max_fee,
+            authority_program_id,
+            mode,
+        ) = array_refs![src, 1, 32, 32, 32, 32, 32, 8, 8, 8, 32, 1, 32, 32, 2, 8, 32, EscrowMode::LEN];
+        let status = EscrowStatus::from_u8(status[0])?;
+        if status == EscrowStatus::Closed {
+            return Err(EscrowClosed.into());
+        }
         Ok(Escrow {
-            is_initialized,
-            is_settled,
+            status,
             payer_pubkey: Pubkey::new_from_array(*payer_pubkey),
             payee_pubkey: Pubkey::new_from_array(
                 *payee_pubkey,
@@ -63,14 +314,22 @@ impl Pack for Escrow {
             fee_taker_pubkey: Pubkey::new_from_array(*fee_taker_pubkey),
             amount: u64::from_le_bytes(*amount),
             fee: u64::from_le_bytes(*fee),
+            accepted_at: i64::from_le_bytes(*accepted_at),
+            rent_payer_pubkey: Pubkey::new_from_array(*rent_payer_pubkey),
+            bump_seed: bump_seed[0],
+            reference: *reference,
+            referrer_pubkey: Pubkey::new_from_array(*referrer_pubkey),
+            referrer_fee_bps: u16::from_le_bytes(*referrer_fee_bps),
+            max_fee: u64::from_le_bytes(*max_fee),
+            authority_program_id: Pubkey::new_from_array(*authority_program_id),
+            mode: EscrowMode::unpack_from_slice(mode)?,
         })
     }
 
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let dst = array_mut_ref![dst, 0, Escrow::LEN];
         let (
-            is_initialized_dst,
-            is_settled_dst,
+            status_dst,
             payer_pubkey_dst,
             payer_receiving_token_account_pubkey_dst,
             payer_temp_token_account_pubkey_dst,
@@ -78,11 +337,19 @@ impl Pack for Escrow {
             fee_taker_pubkey_dst,
             expected_amount_dst,
             expected_fees_dst,
-        ) = mut_array_refs![dst, 1, 1, 32, 32, 32, 32, 32, 8, 8];
+            accepted_at_dst,
+            rent_payer_pubkey_dst,
+            bump_seed_dst,
+            reference_dst,
+            referrer_pubkey_dst,
+            referrer_fee_bps_dst,
+            max_fee_dst,
+            authority_program_id_dst,
+            mode_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 32, 8, 8, 8, 32, 1, 32, 32, 2, 8, 32, EscrowMode::LEN];
 
         let Escrow {
-            is_initialized,
-            is_settled,
+            status,
             payer_pubkey,
             payee_pubkey,
             payer_temp_token_account_pubkey,
@@ -90,10 +357,18 @@ impl Pack for Escrow {
             fee_taker_pubkey,
             amount,
             fee,
+            accepted_at,
+            rent_payer_pubkey,
+            bump_seed,
+            reference,
+            referrer_pubkey,
+            referrer_fee_bps,
+            max_fee,
+            authority_program_id,
+            mode,
         } = self;
 
-        is_initialized_dst[0] = *is_initialized as u8;
-        is_settled_dst[0] = *is_settled as u8;
+        status_dst[0] = *status as u8;
         payer_pubkey_dst.copy_from_slice(payer_pubkey.as_ref());
         payer_receiving_token_account_pubkey_dst
             .copy_from_slice(payee_pubkey.as_ref());
@@ -103,13 +378,122 @@ impl Pack for Escrow {
         fee_taker_pubkey_dst.copy_from_slice(fee_taker_pubkey.as_ref());
         *expected_amount_dst = amount.to_le_bytes();
         *expected_fees_dst = fee.to_le_bytes();
+        *accepted_at_dst = accepted_at.to_le_bytes();
+        rent_payer_pubkey_dst.copy_from_slice(rent_payer_pubkey.as_ref());
+        bump_seed_dst[0] = *bump_seed;
+        *reference_dst = *reference;
+        referrer_pubkey_dst.copy_from_slice(referrer_pubkey.as_ref());
+        *referrer_fee_bps_dst = referrer_fee_bps.to_le_bytes();
+        *max_fee_dst = max_fee.to_le_bytes();
+        authority_program_id_dst.copy_from_slice(authority_program_id.as_ref());
+        mode.pack_into_slice(mode_dst);
     }
 }
 
 impl Sealed for Escrow {}
 
 impl IsInitialized for Escrow {
+    fn is_initialized(&self) -> bool {
+        self.status != EscrowStatus::Uninitialized
+    }
+}
+
+/// Statement written by an attestor, e.g. a shipping partner confirming delivery,
+/// that releases a conditional escrow when its payload matches the escrow's condition
+pub struct Attestation {
+    pub is_initialized: bool,
+    pub attestor_pubkey: Pubkey,
+    pub escrow_pubkey: Pubkey,
+    pub payload: [u8; 32],
+    pub attested_at: UnixTimestamp,
+}
+
+impl Pack for Attestation {
+    const LEN: usize = 105;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Attestation::LEN];
+        let (is_initialized, attestor_pubkey, escrow_pubkey, payload, attested_at) =
+            array_refs![src, 1, 32, 32, 32, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(Attestation {
+            is_initialized,
+            attestor_pubkey: Pubkey::new_from_array(*attestor_pubkey),
+            escrow_pubkey: Pubkey::new_from_array(*escrow_pubkey),
+            payload: *payload,
+            attested_at: i64::from_le_bytes(*attested_at),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Attestation::LEN];
+        let (
+            is_initialized_dst,
+            attestor_pubkey_dst,
+            escrow_pubkey_dst,
+            payload_dst,
+            attested_at_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        attestor_pubkey_dst.copy_from_slice(self.attestor_pubkey.as_ref());
+        escrow_pubkey_dst.copy_from_slice(self.escrow_pubkey.as_ref());
+        *payload_dst = self.payload;
+        *attested_at_dst = self.attested_at.to_le_bytes();
+    }
+}
+
+impl Sealed for Attestation {}
+
+impl IsInitialized for Attestation {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
 }
+
+/// What settling an escrow would pay out, returned by `Quote`
+pub struct SettlementQuote {
+    /// Tokens the payee receives
+    pub payee_amount: u64,
+    /// Tokens the fee taker receives
+    pub fee: u64,
+    /// Lamports of the temp token account returned to the rent payer, on settlement or on close
+    pub vault_rent: u64,
+    /// Lamports of the escrow account returned to the rent payer on close
+    pub escrow_rent: u64,
+    /// Tokens the referrer receives out of the fee, on top of the fee taker's
+    pub referrer_fee: u64,
+}
+
+impl Pack for SettlementQuote {
+    const LEN: usize = 40;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, SettlementQuote::LEN];
+        let (payee_amount, fee, vault_rent, escrow_rent, referrer_fee) =
+            array_refs![src, 8, 8, 8, 8, 8];
+        Ok(SettlementQuote {
+            payee_amount: u64::from_le_bytes(*payee_amount),
+            fee: u64::from_le_bytes(*fee),
+            vault_rent: u64::from_le_bytes(*vault_rent),
+            escrow_rent: u64::from_le_bytes(*escrow_rent),
+            referrer_fee: u64::from_le_bytes(*referrer_fee),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, SettlementQuote::LEN];
+        let (payee_amount_dst, fee_dst, vault_rent_dst, escrow_rent_dst, referrer_fee_dst) =
+            mut_array_refs![dst, 8, 8, 8, 8, 8];
+
+        *payee_amount_dst = self.payee_amount.to_le_bytes();
+        *fee_dst = self.fee.to_le_bytes();
+        *vault_rent_dst = self.vault_rent.to_le_bytes();
+        *escrow_rent_dst = self.escrow_rent.to_le_bytes();
+        *referrer_fee_dst = self.referrer_fee.to_le_bytes();
+    }
+}
+
+impl Sealed for SettlementQuote {}
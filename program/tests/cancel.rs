@@ -0,0 +1,62 @@
+//! Payers cancelling escrows nobody accepted yet.
+
+mod common;
+
+use bpf_program_template::{error::EscrowError, id, instruction, state::EscrowMode};
+use common::{assert_escrow_error, Env, AMOUNT};
+use solana_program::program_pack::Pack;
+use solana_sdk::signature::Signer;
+use spl_token::state::Account as TokenAccount;
+
+#[tokio::test]
+async fn payer_cancels_pending_escrow() {
+    let mut env = Env::start().await;
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+    let payer = env.payer.insecure_clone();
+    let rent_payer = env.context.payer.pubkey();
+
+    let escrow_rent = env.account(&escrow).await.unwrap().lamports;
+    let rent_payer_lamports = env.account(&rent_payer).await.unwrap().lamports;
+    let cancel_ix = instruction::cancel(&id(), &payer.pubkey(), &vault, &escrow, &rent_payer);
+    let transaction = env.transaction(&[cancel_ix], &[&payer]).await;
+    let transaction_fee = env
+        .context
+        .banks_client
+        .get_fee_for_message(transaction.message.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    env.context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+
+    let vault_info = TokenAccount::unpack(&env.account(&vault).await.unwrap().data).unwrap();
+    assert_eq!(vault_info.owner, payer.pubkey());
+    assert_eq!(vault_info.amount, AMOUNT);
+    assert!(env.account(&escrow).await.is_none());
+    assert_eq!(
+        env.account(&rent_payer).await.unwrap().lamports,
+        rent_payer_lamports + escrow_rent - transaction_fee
+    );
+}
+
+#[tokio::test]
+async fn accepted_escrow_not_cancelled() {
+    let mut env = Env::start().await;
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+    env.accept(&escrow).await.unwrap();
+
+    let payer = env.payer.insecure_clone();
+    let cancel_ix = instruction::cancel(
+        &id(),
+        &payer.pubkey(),
+        &vault,
+        &escrow,
+        &env.context.payer.pubkey(),
+    );
+    let result = env.send(&[cancel_ix], &[&payer]).await;
+    assert_escrow_error(result, EscrowError::EscrowAlreadyAccepted);
+    assert_eq!(env.token_balance(&vault).await, AMOUNT);
+}
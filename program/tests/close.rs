@@ -0,0 +1,104 @@
+//! Closing settled escrows: the account keeps a `Closed` tag and cannot be reused.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError,
+    id, instruction,
+    state::{Escrow, EscrowMode, EscrowStatus},
+};
+use common::{assert_escrow_error, Env};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+/// Opens, accepts and settles an escrow, returning it
+async fn settled_escrow(env: &mut Env) -> Pubkey {
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+    env.accept(&escrow).await.unwrap();
+    env.settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await
+        .unwrap();
+    escrow
+}
+
+fn close(env: &Env, escrow: &Pubkey) -> Instruction {
+    instruction::close(
+        &id(),
+        &env.authority.pubkey(),
+        escrow,
+        &env.context.payer.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn closed_escrow_tagged() {
+    let mut env = Env::start().await;
+    let escrow = settled_escrow(&mut env).await;
+    let escrow_rent = env.account(&escrow).await.unwrap().lamports;
+    let rent_payer = env.context.payer.pubkey();
+    let rent_payer_balance = env.account(&rent_payer).await.unwrap().lamports;
+
+    // refund the account in the same transaction so that it survives with its data
+    let refund = Rent::default().minimum_balance(Escrow::LEN);
+    let authority = env.authority.insecure_clone();
+    let transfer_ix = system_instruction::transfer(&rent_payer, &escrow, refund);
+    let close_ix = close(&env, &escrow);
+    let transaction = env
+        .transaction(&[close_ix, transfer_ix], &[&authority])
+        .await;
+    let fee = env
+        .context
+        .banks_client
+        .get_fee_for_message(transaction.message().clone())
+        .await
+        .unwrap()
+        .unwrap();
+    env.context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        env.account(&rent_payer).await.unwrap().lamports,
+        rent_payer_balance + escrow_rent - refund - fee
+    );
+    let account = env.account(&escrow).await.unwrap();
+    assert_eq!(account.owner, id());
+    assert_eq!(account.data[0], EscrowStatus::Closed as u8);
+    assert!(account.data[1..].iter().all(|byte| *byte == 0));
+    assert_eq!(
+        Escrow::unpack(&account.data).err(),
+        Some(EscrowError::EscrowClosed.into())
+    );
+
+    let close_ix = close(&env, &escrow);
+    let result = env.send(&[close_ix], &[&authority]).await;
+    assert_escrow_error(result, EscrowError::EscrowClosed);
+}
+
+#[tokio::test]
+async fn closed_escrow_not_reused_in_transaction() {
+    let mut env = Env::start().await;
+    let escrow = settled_escrow(&mut env).await;
+
+    let authority = env.authority.insecure_clone();
+    let close_ixs = [close(&env, &escrow), close(&env, &escrow)];
+    let result = env.send(&close_ixs, &[&authority]).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(EscrowError::EscrowClosed as u32)
+        )
+    );
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Settled);
+}
@@ -0,0 +1,384 @@
+//! Program-test environment shared by the integration tests: a program with its config created,
+//! a mint and the parties of an escrow.
+#![allow(dead_code)]
+
+use bpf_program_template::{
+    error::EscrowError,
+    find_escrow_address,
+    governance::ConfigParams,
+    id, instruction,
+    processor::Processor,
+    state::{Escrow, EscrowMode},
+};
+use solana_program::{
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::{Clock, UnixTimestamp},
+    instruction::{Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+pub const AMOUNT: u64 = 1_000_000;
+pub const FEE: u64 = 1_000;
+
+pub struct Env {
+    pub context: ProgramTestContext,
+    pub upgrade_authority: Keypair,
+    pub payer: Keypair,
+    pub authority: Keypair,
+    pub payee: Keypair,
+    pub fee_taker: Keypair,
+    pub mint: Pubkey,
+}
+
+/// Config params with `fee_taker` as fee taker and the upgrade authority as admin
+pub fn config_params(admin: Pubkey, fee_taker: Pubkey) -> ConfigParams {
+    ConfigParams {
+        admin,
+        fee_taker,
+        max_fee_bps: 100,
+        paused: false,
+        delay: 0,
+        allowed_mints: vec![],
+    }
+}
+
+/// Adds the native mint, which native token accounts are opened in, to be passed to `start_with`
+pub fn add_native_mint(program_test: &mut ProgramTest) {
+    let mut native_mint = vec![0; Mint::LEN];
+    Mint {
+        mint_authority: COption::None,
+        supply: 0,
+        decimals: 9,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    }
+    .pack_into_slice(&mut native_mint);
+    program_test.add_account(
+        spl_token::native_mint::id(),
+        Account {
+            lamports: Rent::default().minimum_balance(Mint::LEN),
+            data: native_mint,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Asserts that the first instruction of a transaction failed with `error`
+pub fn assert_instruction_error(result: Result<(), BanksClientError>, error: InstructionError) {
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, error)
+    );
+}
+
+/// Asserts that the first instruction of a transaction failed with the escrow `error`
+pub fn assert_escrow_error(result: Result<(), BanksClientError>, error: EscrowError) {
+    assert_instruction_error(result, InstructionError::Custom(error as u32));
+}
+
+impl Env {
+    /// Starts the program and creates its config, then the mint escrows are opened in
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Starts like `start`, `setup` first adding its own programs or accounts to the test
+    pub async fn start_with(setup: impl FnOnce(&mut ProgramTest)) -> Self {
+        let mut program_test =
+            ProgramTest::new("bpf_program_template", id(), processor!(Processor::process));
+        setup(&mut program_test);
+
+        let upgrade_authority = Keypair::new();
+        let (program_data, _) =
+            Pubkey::find_program_address(&[id().as_ref()], &bpf_loader_upgradeable::id());
+        program_test.add_account(
+            program_data,
+            Account::new_data(
+                Rent::default()
+                    .minimum_balance(UpgradeableLoaderState::size_of_programdata_metadata()),
+                &UpgradeableLoaderState::ProgramData {
+                    slot: 0,
+                    upgrade_authority_address: Some(upgrade_authority.pubkey()),
+                },
+                &bpf_loader_upgradeable::id(),
+            )
+            .unwrap(),
+        );
+        let payer = Keypair::new();
+        for wallet in [&upgrade_authority, &payer] {
+            program_test.add_account(
+                wallet.pubkey(),
+                Account::new(1_000_000_000, 0, &system_program::id()),
+            );
+        }
+
+        let mut env = Env {
+            context: program_test.start_with_context().await,
+            upgrade_authority,
+            payer,
+            authority: Keypair::new(),
+            payee: Keypair::new(),
+            fee_taker: Keypair::new(),
+            mint: Pubkey::default(),
+        };
+        let init_config_ix = instruction::init_config(
+            &id(),
+            &env.upgrade_authority.pubkey(),
+            config_params(env.upgrade_authority.pubkey(), env.fee_taker.pubkey()),
+        );
+        let upgrade_authority = env.upgrade_authority.insecure_clone();
+        env.send(&[init_config_ix], &[&upgrade_authority])
+            .await
+            .unwrap();
+        env.mint = env.create_mint().await;
+        env
+    }
+
+    /// Signs the instructions into a transaction paid by the context payer, on a fresh blockhash
+    /// so identical transactions can be sent again
+    pub async fn transaction(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Transaction {
+        self.context.last_blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        )
+    }
+
+    /// Sends the instructions in a transaction paid by the context payer
+    pub async fn send(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        let transaction = self.transaction(instructions, signers).await;
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    /// Creates a rent exempt account of `space` bytes owned by `owner`
+    pub async fn create_account(&mut self, account: &Keypair, space: usize, owner: &Pubkey) {
+        let create_ix = system_instruction::create_account(
+            &self.context.payer.pubkey(),
+            &account.pubkey(),
+            Rent::default().minimum_balance(space),
+            space as u64,
+            owner,
+        );
+        self.send(&[create_ix], &[account]).await.unwrap();
+    }
+
+    pub async fn create_mint(&mut self) -> Pubkey {
+        let mint = Keypair::new();
+        self.create_account(&mint, Mint::LEN, &spl_token::id())
+            .await;
+        let init_mint_ix = spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &self.context.payer.pubkey(),
+            None,
+            0,
+        )
+        .unwrap();
+        self.send(&[init_mint_ix], &[]).await.unwrap();
+        mint.pubkey()
+    }
+
+    /// Creates a token account of `mint` owned by `owner` holding `amount`
+    pub async fn create_token_account_of(
+        &mut self,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> Pubkey {
+        let account = Keypair::new();
+        self.create_account(&account, TokenAccount::LEN, &spl_token::id())
+            .await;
+        let init_account_ix = spl_token::instruction::initialize_account(
+            &spl_token::id(),
+            &account.pubkey(),
+            mint,
+            owner,
+        )
+        .unwrap();
+        let mint_to_ix = spl_token::instruction::mint_to(
+            &spl_token::id(),
+            mint,
+            &account.pubkey(),
+            &self.context.payer.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap();
+        self.send(&[init_account_ix, mint_to_ix], &[])
+            .await
+            .unwrap();
+        account.pubkey()
+    }
+
+    /// Creates a token account of the escrow mint owned by `owner` holding `amount`
+    pub async fn create_token_account(&mut self, owner: &Pubkey, amount: u64) -> Pubkey {
+        let mint = self.mint;
+        self.create_token_account_of(&mint, owner, amount).await
+    }
+
+    /// Creates a native token account owned by `owner`, wrapping `lamports`. The native mint has
+    /// to be added with `add_native_mint`
+    pub async fn create_native_account(&mut self, owner: &Pubkey, lamports: u64) -> Pubkey {
+        let account = Keypair::new();
+        let create_ix = system_instruction::create_account(
+            &self.context.payer.pubkey(),
+            &account.pubkey(),
+            Rent::default().minimum_balance(TokenAccount::LEN) + lamports,
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        );
+        let init_account_ix = spl_token::instruction::initialize_account(
+            &spl_token::id(),
+            &account.pubkey(),
+            &spl_token::native_mint::id(),
+            owner,
+        )
+        .unwrap();
+        self.send(&[create_ix, init_account_ix], &[&account])
+            .await
+            .unwrap();
+        account.pubkey()
+    }
+
+    pub async fn token_balance(&mut self, account: &Pubkey) -> u64 {
+        let account = self
+            .context
+            .banks_client
+            .get_account(*account)
+            .await
+            .unwrap()
+            .unwrap();
+        TokenAccount::unpack(&account.data).unwrap().amount
+    }
+
+    /// The account at `address`, `None` once it is closed
+    pub async fn account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+    }
+
+    pub async fn escrow(&mut self, escrow: &Pubkey) -> Escrow {
+        let account = self
+            .context
+            .banks_client
+            .get_account(*escrow)
+            .await
+            .unwrap()
+            .unwrap();
+        Escrow::unpack(&account.data).unwrap()
+    }
+
+    /// Moves the clock to `unix_timestamp`
+    pub async fn set_time(&mut self, unix_timestamp: UnixTimestamp) {
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub async fn now(&mut self) -> UnixTimestamp {
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp
+    }
+
+    /// Opens an escrow of `AMOUNT` in `mode`, returning its address and temp token account
+    pub async fn init_escrow(
+        &mut self,
+        mode: EscrowMode,
+    ) -> Result<(Pubkey, Pubkey), BanksClientError> {
+        self.init_escrow_with(mode, None, 0).await
+    }
+
+    /// Opens an escrow of `AMOUNT` in `mode`, paying `referrer_fee_bps` of the fee to `referrer`
+    pub async fn init_escrow_with(
+        &mut self,
+        mode: EscrowMode,
+        referrer: Option<&Pubkey>,
+        referrer_fee_bps: u16,
+    ) -> Result<(Pubkey, Pubkey), BanksClientError> {
+        let payer = self.payer.insecure_clone();
+        let authority = self.authority.insecure_clone();
+        let vault = self.create_token_account(&payer.pubkey(), AMOUNT).await;
+        let reference = Keypair::new().pubkey().to_bytes();
+        let init_escrow_ix = instruction::init_escrow(
+            &id(),
+            &payer.pubkey(),
+            &vault,
+            &authority.pubkey(),
+            &self.context.payer.pubkey(),
+            &self.fee_taker.pubkey(),
+            referrer,
+            AMOUNT,
+            reference,
+            referrer_fee_bps,
+            FEE,
+            mode,
+            None,
+        );
+        self.send(&[init_escrow_ix], &[&payer, &authority]).await?;
+        let (escrow, _) =
+            find_escrow_address(&payer.pubkey(), &authority.pubkey(), &reference, &id());
+        Ok((escrow, vault))
+    }
+
+    /// Accepts the escrow as the payee, vouched for by the authority
+    pub async fn accept(&mut self, escrow: &Pubkey) -> Result<(), BanksClientError> {
+        let payee = self.payee.insecure_clone();
+        let authority = self.authority.insecure_clone();
+        let accept_ix = instruction::accept(&id(), &payee.pubkey(), &authority.pubkey(), escrow);
+        self.send(&[accept_ix], &[&payee, &authority]).await
+    }
+
+    /// Settles the escrow with `FEE` to the payee's and fee taker's token accounts
+    pub async fn settle(
+        &mut self,
+        escrow: &Pubkey,
+        vault: &Pubkey,
+        payee_account: &Pubkey,
+        fee_taker_account: &Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let authority = self.authority.insecure_clone();
+        let settle_ix = instruction::settle(
+            &id(),
+            &authority.pubkey(),
+            payee_account,
+            fee_taker_account,
+            vault,
+            escrow,
+            &self.context.payer.pubkey(),
+            None,
+            FEE,
+        );
+        self.send(&[settle_ix], &[&authority]).await
+    }
+}
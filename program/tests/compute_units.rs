@@ -0,0 +1,207 @@
+//! Compute units consumed by the escrow instructions.
+//!
+//! Run with `cargo test-bpf --test compute_units -- --nocapture` to benchmark the deployed BPF
+//! program. Every instruction is then compared with its baseline in `tests/compute_units.txt`
+//! and the run fails when one regresses by more than `CU_REGRESSION_PERCENT` (5 by default).
+//! `UPDATE_CU_BASELINES=1` records the measured numbers as the new baselines instead.
+//!
+//! Under a plain `cargo test` the program runs natively, its own instructions are not metered,
+//! and the numbers are only printed.
+
+mod common;
+
+use std::{collections::BTreeMap, env, fs};
+
+use bpf_program_template::{find_escrow_address, id, instruction, state::EscrowMode};
+use common::{add_native_mint, Env, AMOUNT, FEE};
+use solana_program::{instruction::Instruction, pubkey::Pubkey, rent::Rent, system_instruction};
+use solana_sdk::signature::{Keypair, Signer};
+
+const BASELINES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/compute_units.txt");
+const DEFAULT_REGRESSION_PERCENT: u64 = 5;
+
+struct Bench {
+    env: Env,
+    units: BTreeMap<&'static str, u64>,
+}
+
+impl Bench {
+    /// Starts the program with the native mint, which native temp token accounts are opened in
+    async fn start() -> Self {
+        let env = Env::start_with(add_native_mint).await;
+        Bench {
+            env,
+            units: BTreeMap::new(),
+        }
+    }
+
+    /// Sends the instructions, recording the compute units they consume under `name`
+    async fn send(
+        &mut self,
+        name: &'static str,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) {
+        let transaction = self.env.transaction(instructions, signers).await;
+        let simulation = self
+            .env
+            .context
+            .banks_client
+            .simulate_transaction(transaction.clone())
+            .await
+            .unwrap();
+        let details = simulation.simulation_details.unwrap();
+        if let Some(Err(err)) = simulation.result {
+            panic!("{} failed: {:?}\n{:#?}", name, err, details.logs);
+        }
+        self.units.insert(name, details.units_consumed);
+
+        self.env
+            .context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap();
+    }
+
+    /// Runs an escrow of `AMOUNT` held in `vault` through InitEscrow, Accept, Quote, Settle and
+    /// Close, benchmarking the settlement as `settle_name`
+    async fn run_escrow(
+        &mut self,
+        vault: Pubkey,
+        payee_account: Pubkey,
+        fee_taker_account: Pubkey,
+        settle_name: &'static str,
+    ) {
+        let payer = self.env.payer.insecure_clone();
+        let authority = self.env.authority.insecure_clone();
+        let payee = self.env.payee.insecure_clone();
+        let rent_payer = self.env.context.payer.pubkey();
+        let reference = Keypair::new().pubkey().to_bytes();
+        let (escrow, _) =
+            find_escrow_address(&payer.pubkey(), &authority.pubkey(), &reference, &id());
+
+        let init_escrow_ix = instruction::init_escrow(
+            &id(),
+            &payer.pubkey(),
+            &vault,
+            &authority.pubkey(),
+            &rent_payer,
+            &self.env.fee_taker.pubkey(),
+            None,
+            AMOUNT,
+            reference,
+            0,
+            FEE,
+            EscrowMode::Standard,
+            None,
+        );
+        self.send("init_escrow", &[init_escrow_ix], &[&payer, &authority])
+            .await;
+
+        let accept_ix = instruction::accept(&id(), &payee.pubkey(), &authority.pubkey(), &escrow);
+        self.send("accept", &[accept_ix], &[&payee, &authority])
+            .await;
+
+        let quote_ix = instruction::quote(&id(), &escrow, &vault, FEE);
+        self.send("quote", &[quote_ix], &[]).await;
+
+        let settle_ix = instruction::settle(
+            &id(),
+            &authority.pubkey(),
+            &payee_account,
+            &fee_taker_account,
+            &vault,
+            &escrow,
+            &rent_payer,
+            None,
+            FEE,
+        );
+        self.send(settle_name, &[settle_ix], &[&authority]).await;
+
+        let close_ix = instruction::close(&id(), &authority.pubkey(), &escrow, &rent_payer);
+        self.send("close", &[close_ix], &[&authority]).await;
+    }
+}
+
+/// Fails on any instruction consuming more than its baseline allows, or records the baselines
+fn check_baselines(units: &BTreeMap<&'static str, u64>) {
+    if env::var("UPDATE_CU_BASELINES").is_ok() {
+        let baselines: String = units
+            .iter()
+            .map(|(name, units)| format!("{} {}\n", name, units))
+            .collect();
+        fs::write(BASELINES, baselines).unwrap();
+        return;
+    }
+
+    let threshold = env::var("CU_REGRESSION_PERCENT")
+        .map(|percent| {
+            percent
+                .parse()
+                .expect("CU_REGRESSION_PERCENT must be a number")
+        })
+        .unwrap_or(DEFAULT_REGRESSION_PERCENT);
+    let baselines = fs::read_to_string(BASELINES).unwrap_or_default();
+    let baselines: BTreeMap<&str, u64> = baselines
+        .lines()
+        .filter_map(|line| {
+            let (name, units) = line.split_once(' ')?;
+            Some((name, units.trim().parse().ok()?))
+        })
+        .collect();
+
+    let regressions: Vec<String> = units
+        .iter()
+        .filter_map(|(name, units)| match baselines.get(name) {
+            None => Some(format!(
+                "{}: {} CU, no baseline recorded, run with UPDATE_CU_BASELINES=1 and commit {}",
+                name, units, BASELINES
+            )),
+            Some(baseline) if *units * 100 > baseline * (100 + threshold) => {
+                Some(format!("{}: {} CU, baseline {} CU", name, units, baseline))
+            }
+            Some(_) => None,
+        })
+        .collect();
+    assert!(
+        regressions.is_empty(),
+        "compute units regressed by more than {}%:\n{}",
+        threshold,
+        regressions.join("\n")
+    );
+}
+
+#[tokio::test]
+async fn compute_units() {
+    let mut bench = Bench::start().await;
+    let payer = bench.env.payer.pubkey();
+    let payee = bench.env.payee.pubkey();
+    let fee_taker = bench.env.fee_taker.pubkey();
+
+    let vault = bench.env.create_token_account(&payer, AMOUNT).await;
+    let payee_account = bench.env.create_token_account(&payee, 0).await;
+    let fee_taker_account = bench.env.create_token_account(&fee_taker, 0).await;
+    bench
+        .run_escrow(vault, payee_account, fee_taker_account, "settle_spl")
+        .await;
+
+    // native escrows pay their wallets in lamports, which have to stay rent exempt
+    let native_vault = bench.env.create_native_account(&payer, AMOUNT).await;
+    let rent = Rent::default().minimum_balance(0);
+    for wallet in [payee, fee_taker] {
+        let transfer_ix =
+            system_instruction::transfer(&bench.env.context.payer.pubkey(), &wallet, rent);
+        bench.env.send(&[transfer_ix], &[]).await.unwrap();
+    }
+    bench
+        .run_escrow(native_vault, payee, fee_taker, "settle_native")
+        .await;
+
+    for (name, units) in &bench.units {
+        println!("{:<16} {:>8} CU", name, units);
+    }
+    if env::var("BPF_OUT_DIR").is_ok() || env::var("SBF_OUT_DIR").is_ok() {
+        check_baselines(&bench.units);
+    }
+}
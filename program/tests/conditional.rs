@@ -0,0 +1,104 @@
+//! Conditional escrows settled by the payee once the attestor has attested the condition.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError,
+    id, instruction,
+    state::{Attestation, EscrowMode, EscrowStatus},
+};
+use common::{assert_escrow_error, Env, AMOUNT};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::BanksClientError;
+use solana_sdk::signature::{Keypair, Signer};
+
+const CONDITION: [u8; 32] = [7; 32];
+
+/// Opens and accepts an escrow settled once `attestor` attests `CONDITION`
+async fn init_conditional(env: &mut Env, attestor: &Pubkey) -> (Pubkey, Pubkey) {
+    let (escrow, vault) = env
+        .init_escrow(EscrowMode::Conditional {
+            attestor: *attestor,
+            condition: CONDITION,
+        })
+        .await
+        .unwrap();
+    env.accept(&escrow).await.unwrap();
+    (escrow, vault)
+}
+
+/// Records `payload` about `escrow` signed by `attestor`, returning the attestation account
+async fn attest(env: &mut Env, attestor: &Keypair, escrow: &Pubkey, payload: [u8; 32]) -> Pubkey {
+    let attestation = Keypair::new();
+    env.create_account(&attestation, Attestation::LEN, &id())
+        .await;
+    let attest_ix = instruction::attest(
+        &id(),
+        &attestor.pubkey(),
+        &attestation.pubkey(),
+        escrow,
+        payload,
+    );
+    env.send(&[attest_ix], &[attestor]).await.unwrap();
+    attestation.pubkey()
+}
+
+async fn settle_by_condition(
+    env: &mut Env,
+    escrow: &Pubkey,
+    vault: &Pubkey,
+    payee_account: &Pubkey,
+    attestation: &Pubkey,
+) -> Result<(), BanksClientError> {
+    let settle_ix = instruction::settle_by_condition(
+        &id(),
+        payee_account,
+        vault,
+        &env.context.payer.pubkey(),
+        escrow,
+        attestation,
+    );
+    env.send(&[settle_ix], &[]).await
+}
+
+#[tokio::test]
+async fn attested_condition_pays_payee() {
+    let mut env = Env::start().await;
+    let attestor = Keypair::new();
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let (escrow, vault) = init_conditional(&mut env, &attestor.pubkey()).await;
+
+    let attestation = attest(&mut env, &attestor, &escrow, CONDITION).await;
+    let attestation_info =
+        Attestation::unpack(&env.account(&attestation).await.unwrap().data).unwrap();
+    assert_eq!(attestation_info.attestor_pubkey, attestor.pubkey());
+    assert_eq!(attestation_info.escrow_pubkey, escrow);
+    assert_eq!(attestation_info.payload, CONDITION);
+
+    settle_by_condition(&mut env, &escrow, &vault, &payee_account, &attestation)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT);
+    assert!(env.account(&vault).await.is_none());
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Settled);
+}
+
+#[tokio::test]
+async fn mismatched_attestation_rejected() {
+    let mut env = Env::start().await;
+    let attestor = Keypair::new();
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let (escrow, vault) = init_conditional(&mut env, &attestor.pubkey()).await;
+    let (other_escrow, _) = init_conditional(&mut env, &attestor.pubkey()).await;
+
+    let wrong_attestor = attest(&mut env, &Keypair::new(), &escrow, CONDITION).await;
+    let wrong_payload = attest(&mut env, &attestor, &escrow, [8; 32]).await;
+    let wrong_escrow = attest(&mut env, &attestor, &other_escrow, CONDITION).await;
+    for attestation in [wrong_attestor, wrong_payload, wrong_escrow] {
+        let result =
+            settle_by_condition(&mut env, &escrow, &vault, &payee_account, &attestation).await;
+        assert_escrow_error(result, EscrowError::ConditionNotMet);
+    }
+    assert_eq!(env.token_balance(&vault).await, AMOUNT);
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Active);
+}
@@ -0,0 +1,58 @@
+//! Topping up open escrows with `Deposit`.
+
+mod common;
+
+use bpf_program_template::{
+    id, instruction,
+    state::{EscrowMode, EscrowStatus},
+};
+use common::{Env, AMOUNT, FEE};
+use solana_program::{instruction::InstructionError, system_program};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+#[tokio::test]
+async fn deposit_adds_to_escrow() {
+    let mut env = Env::start().await;
+    let payer = env.payer.insecure_clone();
+    let payer_account = env.create_token_account(&payer.pubkey(), 500).await;
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+
+    let deposit_ix =
+        instruction::deposit(&id(), &payer.pubkey(), &payer_account, &vault, &escrow, 200);
+    env.send(&[deposit_ix], &[&payer]).await.unwrap();
+    assert_eq!(env.escrow(&escrow).await.amount, AMOUNT + 200);
+    assert_eq!(env.token_balance(&vault).await, AMOUNT + 200);
+    assert_eq!(env.token_balance(&payer_account).await, 300);
+
+    // still open once accepted, and the payee is settled everything deposited
+    env.accept(&escrow).await.unwrap();
+    let deposit_ix =
+        instruction::deposit(&id(), &payer.pubkey(), &payer_account, &vault, &escrow, 300);
+    env.send(&[deposit_ix], &[&payer]).await.unwrap();
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+    env.settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT + 500 - FEE);
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Settled);
+}
+
+#[tokio::test]
+async fn deposit_requires_token_program() {
+    let mut env = Env::start().await;
+    let payer = env.payer.insecure_clone();
+    let payer_account = env.create_token_account(&payer.pubkey(), 500).await;
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+
+    // a program doing nothing with the transfer cannot inflate the escrowed amount
+    let mut deposit_ix =
+        instruction::deposit(&id(), &payer.pubkey(), &payer_account, &vault, &escrow, 500);
+    deposit_ix.accounts[4].pubkey = system_program::id();
+    let result = env.send(&[deposit_ix], &[&payer]).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)
+    );
+    assert_eq!(env.escrow(&escrow).await.amount, AMOUNT);
+}
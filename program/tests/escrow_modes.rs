@@ -0,0 +1,296 @@
+//! Escrow modes released by their own instructions, which the authority cannot bypass.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError,
+    id, instruction,
+    state::{EscrowMode, EscrowStatus},
+};
+use common::{assert_escrow_error, Env, AMOUNT};
+use solana_program::{clock::UnixTimestamp, hash::hash, program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::BanksClientError;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token::state::Account as TokenAccount;
+
+#[tokio::test]
+async fn swap_cannot_be_accepted_and_settled() {
+    let mut env = Env::start().await;
+    let other_mint = env.create_mint().await;
+    let payer = env.payer.pubkey();
+    let receive_account = env.create_token_account_of(&other_mint, &payer, 0).await;
+    let (escrow, vault) = env
+        .init_escrow(EscrowMode::Swap {
+            receive_account,
+            expected_amount: 500,
+        })
+        .await
+        .unwrap();
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+
+    let result = env.accept(&escrow).await;
+    assert_escrow_error(result, EscrowError::InvalidEscrowMode);
+    let result = env
+        .settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await;
+    assert_escrow_error(result, EscrowError::InvalidEscrowMode);
+    assert_eq!(env.token_balance(&payee_account).await, 0);
+}
+
+const PREIMAGE: [u8; 32] = [9; 32];
+
+/// Opens a hash locked escrow to the payee expiring in 100 seconds
+async fn init_hash_lock(env: &mut Env) -> (Pubkey, Pubkey, UnixTimestamp) {
+    let expires_at = env.now().await + 100;
+    let (escrow, vault) = env
+        .init_escrow(EscrowMode::HashLock {
+            hash: hash(&PREIMAGE).to_bytes(),
+            expires_at,
+            payee: env.payee.pubkey(),
+        })
+        .await
+        .unwrap();
+    (escrow, vault, expires_at)
+}
+
+/// Accepts the escrow as `payee`, without the authority signing
+async fn accept_as(
+    env: &mut Env,
+    payee: &Keypair,
+    escrow: &Pubkey,
+) -> Result<(), BanksClientError> {
+    let mut accept_ix =
+        instruction::accept(&id(), &payee.pubkey(), &env.authority.pubkey(), escrow);
+    accept_ix.accounts[1].is_signer = false;
+    env.send(&[accept_ix], &[payee]).await
+}
+
+/// Accepts the escrow as the payee, without the authority signing
+async fn accept_alone(env: &mut Env, escrow: &Pubkey) -> Result<(), BanksClientError> {
+    let payee = env.payee.insecure_clone();
+    accept_as(env, &payee, escrow).await
+}
+
+async fn claim_as(
+    env: &mut Env,
+    payee: &Keypair,
+    escrow: &Pubkey,
+    vault: &Pubkey,
+    payee_account: &Pubkey,
+    preimage: [u8; 32],
+) -> Result<(), BanksClientError> {
+    let claim_ix = instruction::claim(
+        &id(),
+        &payee.pubkey(),
+        payee_account,
+        vault,
+        &env.context.payer.pubkey(),
+        escrow,
+        preimage,
+    );
+    env.send(&[claim_ix], &[payee]).await
+}
+
+async fn claim(
+    env: &mut Env,
+    escrow: &Pubkey,
+    vault: &Pubkey,
+    payee_account: &Pubkey,
+    preimage: [u8; 32],
+) -> Result<(), BanksClientError> {
+    let payee = env.payee.insecure_clone();
+    claim_as(env, &payee, escrow, vault, payee_account, preimage).await
+}
+
+#[tokio::test]
+async fn hash_lock_claimed_before_expiry_without_authority() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+
+    // claimed straight from pending
+    let (escrow, vault, _) = init_hash_lock(&mut env).await;
+    claim(&mut env, &escrow, &vault, &payee_account, PREIMAGE)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT);
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Settled);
+
+    // accepted by the payee first, the authority can neither settle it
+    let (escrow, vault, _) = init_hash_lock(&mut env).await;
+    accept_alone(&mut env, &escrow).await.unwrap();
+    let result = env
+        .settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await;
+    assert_escrow_error(result, EscrowError::InvalidEscrowMode);
+    claim(&mut env, &escrow, &vault, &payee_account, PREIMAGE)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, 2 * AMOUNT);
+}
+
+#[tokio::test]
+async fn hash_lock_only_taken_by_its_payee() {
+    let mut env = Env::start().await;
+    let stranger = Keypair::new();
+    let stranger_account = env.create_token_account(&stranger.pubkey(), 0).await;
+    let (escrow, vault, _) = init_hash_lock(&mut env).await;
+
+    let result = accept_as(&mut env, &stranger, &escrow).await;
+    assert_escrow_error(result, EscrowError::PayeeMismatch);
+    // the preimage alone does not make its holder the payee
+    let result = claim_as(
+        &mut env,
+        &stranger,
+        &escrow,
+        &vault,
+        &stranger_account,
+        PREIMAGE,
+    )
+    .await;
+    assert_escrow_error(result, EscrowError::PayeeMismatch);
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Pending);
+}
+
+#[tokio::test]
+async fn hash_lock_not_cancelled() {
+    let mut env = Env::start().await;
+    let (escrow, vault, _) = init_hash_lock(&mut env).await;
+
+    let payer = env.payer.insecure_clone();
+    let cancel_ix = instruction::cancel(
+        &id(),
+        &payer.pubkey(),
+        &vault,
+        &escrow,
+        &env.context.payer.pubkey(),
+    );
+    let result = env.send(&[cancel_ix], &[&payer]).await;
+    assert_escrow_error(result, EscrowError::InvalidEscrowMode);
+    assert_eq!(env.token_balance(&vault).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn hash_lock_rejects_wrong_preimage() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let (escrow, vault, _) = init_hash_lock(&mut env).await;
+
+    let result = claim(&mut env, &escrow, &vault, &payee_account, [8; 32]).await;
+    assert_escrow_error(result, EscrowError::InvalidPreimage);
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Pending);
+}
+
+/// Checks the payer owns the temp token account again
+async fn assert_vault_returned(env: &mut Env, vault: &Pubkey) {
+    let vault_account = env
+        .context
+        .banks_client
+        .get_account(*vault)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        TokenAccount::unpack(&vault_account.data).unwrap().owner,
+        env.payer.pubkey()
+    );
+}
+
+#[tokio::test]
+async fn hash_lock_refunded_after_expiry_without_acceptance() {
+    let mut env = Env::start().await;
+    let (escrow, vault, expires_at) = init_hash_lock(&mut env).await;
+
+    let payer = env.payer.insecure_clone();
+    let refund_ix = instruction::refund(&id(), &payer.pubkey(), &vault, &escrow);
+    let result = env.send(std::slice::from_ref(&refund_ix), &[&payer]).await;
+    assert_escrow_error(result, EscrowError::HashLockNotExpired);
+
+    env.set_time(expires_at).await;
+    env.send(&[refund_ix], &[&payer]).await.unwrap();
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Refunded);
+    assert_vault_returned(&mut env, &vault).await;
+}
+
+#[tokio::test]
+async fn hash_lock_refunded_after_expiry() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let (escrow, vault, expires_at) = init_hash_lock(&mut env).await;
+    accept_alone(&mut env, &escrow).await.unwrap();
+
+    let payer = env.payer.insecure_clone();
+    let refund_ix = instruction::refund(&id(), &payer.pubkey(), &vault, &escrow);
+    let result = env.send(std::slice::from_ref(&refund_ix), &[&payer]).await;
+    assert_escrow_error(result, EscrowError::HashLockNotExpired);
+
+    env.set_time(expires_at).await;
+    let result = claim(&mut env, &escrow, &vault, &payee_account, PREIMAGE).await;
+    assert_escrow_error(result, EscrowError::EscrowExpired);
+    env.send(&[refund_ix], &[&payer]).await.unwrap();
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Refunded);
+    assert_vault_returned(&mut env, &vault).await;
+}
+
+/// Accepts the escrow, then checks neither `Settle` nor `SettleMany` pay it out
+async fn assert_not_settled_by_authority(env: &mut Env, mode: EscrowMode) {
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+    let (escrow, vault) = env.init_escrow(mode).await.unwrap();
+    env.accept(&escrow).await.unwrap();
+
+    let result = env
+        .settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await;
+    assert_escrow_error(result, EscrowError::InvalidEscrowMode);
+
+    let authority = env.authority.insecure_clone();
+    let settle_many_ix = instruction::settle_many(
+        &id(),
+        &authority.pubkey(),
+        &fee_taker_account,
+        &env.context.payer.pubkey(),
+        &[(escrow, vault, payee_account, None)],
+        10,
+    );
+    let result = env.send(&[settle_many_ix], &[&authority]).await;
+    assert_escrow_error(result, EscrowError::InvalidEscrowMode);
+
+    assert_eq!(env.token_balance(&vault).await, AMOUNT);
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Active);
+}
+
+#[tokio::test]
+async fn vesting_not_settled_by_authority() {
+    let mut env = Env::start().await;
+    let now = env.now().await;
+    let mode = EscrowMode::Vesting {
+        start_at: now,
+        cliff_at: now + 50,
+        end_at: now + 100,
+        released: 0,
+    };
+    assert_not_settled_by_authority(&mut env, mode).await;
+}
+
+#[tokio::test]
+async fn recurring_not_settled_by_authority() {
+    let mut env = Env::start().await;
+    let mode = EscrowMode::Recurring {
+        period: 10,
+        amount_per_period: AMOUNT / 4,
+        released_periods: 0,
+    };
+    assert_not_settled_by_authority(&mut env, mode).await;
+}
+
+#[tokio::test]
+async fn conditional_not_settled_by_authority() {
+    let mut env = Env::start().await;
+    let mode = EscrowMode::Conditional {
+        attestor: Pubkey::new_unique(),
+        condition: [1; 32],
+    };
+    assert_not_settled_by_authority(&mut env, mode).await;
+}
@@ -0,0 +1,80 @@
+//! Fees bounded by the max fee agreed by the payer and by the config's fee rate.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError, find_escrow_address, id, instruction, state::EscrowMode,
+};
+use common::{assert_escrow_error, Env, AMOUNT, FEE};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::BanksClientError;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Opens an escrow of `AMOUNT` the payer pays at most `max_fee` of
+async fn init_escrow_with_max_fee(env: &mut Env, max_fee: u64) -> Result<Pubkey, BanksClientError> {
+    let payer = env.payer.insecure_clone();
+    let authority = env.authority.insecure_clone();
+    let vault = env.create_token_account(&payer.pubkey(), AMOUNT).await;
+    let reference = Keypair::new().pubkey().to_bytes();
+    let init_escrow_ix = instruction::init_escrow(
+        &id(),
+        &payer.pubkey(),
+        &vault,
+        &authority.pubkey(),
+        &env.context.payer.pubkey(),
+        &env.fee_taker.pubkey(),
+        None,
+        AMOUNT,
+        reference,
+        0,
+        max_fee,
+        EscrowMode::Standard,
+        None,
+    );
+    env.send(&[init_escrow_ix], &[&payer, &authority]).await?;
+    Ok(find_escrow_address(&payer.pubkey(), &authority.pubkey(), &reference, &id()).0)
+}
+
+#[tokio::test]
+async fn settle_fee_above_max_fee() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+    env.accept(&escrow).await.unwrap();
+
+    let authority = env.authority.insecure_clone();
+    let settle_ix = instruction::settle(
+        &id(),
+        &authority.pubkey(),
+        &payee_account,
+        &fee_taker_account,
+        &vault,
+        &escrow,
+        &env.context.payer.pubkey(),
+        None,
+        FEE + 1,
+    );
+    let result = env.send(&[settle_ix], &[&authority]).await;
+    assert_escrow_error(result, EscrowError::FeeAboveMaximum);
+
+    env.settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&fee_taker_account).await, FEE);
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT - FEE);
+}
+
+#[tokio::test]
+async fn init_max_fee_above_config_rate() {
+    let mut env = Env::start().await;
+
+    // the config allows 100 basis points of the amount
+    let result = init_escrow_with_max_fee(&mut env, AMOUNT / 100 + 1).await;
+    assert_escrow_error(result.map(|_| ()), EscrowError::FeeRateAboveMaximum);
+
+    let escrow = init_escrow_with_max_fee(&mut env, AMOUNT / 100)
+        .await
+        .unwrap();
+    assert_eq!(env.escrow(&escrow).await.max_fee, AMOUNT / 100);
+}
@@ -0,0 +1,106 @@
+//! Config changes going through the governance timelock, and what the config enforces.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError, governance::ConfigParams, id, instruction, state::EscrowMode,
+};
+use common::{assert_escrow_error, config_params, Env};
+use solana_program_test::BanksClientError;
+use solana_sdk::signature::{Keypair, Signer};
+
+impl Env {
+    fn params(&self) -> ConfigParams {
+        config_params(self.upgrade_authority.pubkey(), self.fee_taker.pubkey())
+    }
+}
+
+async fn propose(env: &mut Env, admin: &Keypair, params: ConfigParams) {
+    let propose_ix = instruction::propose_config_change(&id(), &admin.pubkey(), params);
+    env.send(&[propose_ix], &[admin]).await.unwrap();
+}
+
+async fn execute(env: &mut Env) -> Result<(), BanksClientError> {
+    env.send(&[instruction::execute_config_change(&id())], &[])
+        .await
+}
+
+/// Changes the config right away, its delay still being 0
+async fn change_config(env: &mut Env, params: ConfigParams) {
+    let admin = env.upgrade_authority.insecure_clone();
+    propose(env, &admin, params).await;
+    execute(env).await.unwrap();
+}
+
+#[tokio::test]
+async fn execute_waits_for_delay() {
+    let mut env = Env::start().await;
+    let params = env.params();
+    let admin = env.upgrade_authority.insecure_clone();
+    change_config(
+        &mut env,
+        ConfigParams {
+            delay: 100,
+            ..params.clone()
+        },
+    )
+    .await;
+
+    let eta = env.now().await + 100;
+    propose(
+        &mut env,
+        &admin,
+        ConfigParams {
+            max_fee_bps: 50,
+            ..params.clone()
+        },
+    )
+    .await;
+    let result = execute(&mut env).await;
+    assert_escrow_error(result, EscrowError::ConfigChangeNotDue);
+
+    env.set_time(eta).await;
+    execute(&mut env).await.unwrap();
+}
+
+#[tokio::test]
+async fn only_admin_proposes() {
+    let mut env = Env::start().await;
+    let params = env.params();
+    let payer = env.payer.insecure_clone();
+    let propose_ix = instruction::propose_config_change(&id(), &payer.pubkey(), params);
+    let result = env.send(&[propose_ix], &[&payer]).await;
+    assert_escrow_error(result, EscrowError::AdminMismatch);
+}
+
+#[tokio::test]
+async fn execute_needs_pending_change() {
+    let mut env = Env::start().await;
+    let params = env.params();
+    let result = execute(&mut env).await;
+    assert_escrow_error(result, EscrowError::NoPendingConfigChange);
+
+    // the change is gone once executed
+    change_config(&mut env, params.clone()).await;
+    let result = execute(&mut env).await;
+    assert_escrow_error(result, EscrowError::NoPendingConfigChange);
+}
+
+#[tokio::test]
+async fn paused_config_blocks_new_escrows() {
+    let mut env = Env::start().await;
+    let params = env.params();
+    change_config(
+        &mut env,
+        ConfigParams {
+            paused: true,
+            ..params.clone()
+        },
+    )
+    .await;
+    let result = env.init_escrow(EscrowMode::Standard).await;
+    assert_escrow_error(result.map(|_| ()), EscrowError::EscrowsPaused);
+
+    change_config(&mut env, params.clone()).await;
+    env.init_escrow(EscrowMode::Standard).await.unwrap();
+}
@@ -0,0 +1,156 @@
+//! Escrows whose authority is a PDA of another program, here a minimal auction program
+//! forwarding escrow instructions and signing them with its authority PDA.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError,
+    find_escrow_address, id,
+    instruction::{self, AuthorityProgram},
+    state::EscrowMode,
+};
+use common::{assert_escrow_error, Env, AMOUNT, FEE};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    pubkey::Pubkey,
+};
+use solana_program_test::{processor, BanksClientError};
+use solana_sdk::signature::Signer;
+
+fn auction_id() -> Pubkey {
+    Pubkey::new_from_array([7; 32])
+}
+
+fn auction_authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"auction"], &auction_id())
+}
+
+/// The auction program: invokes the escrow instruction it is given, its first account being the
+/// escrow program, with its authority PDA signing
+fn process_auction(_: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let (authority, bump_seed) = auction_authority();
+    let (escrow_program, accounts) = accounts.split_first().unwrap();
+    let ix = Instruction {
+        program_id: *escrow_program.key,
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer || *account.key == authority,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: data.to_vec(),
+    };
+    invoke_signed(&ix, accounts, &[&[b"auction", &[bump_seed]]])
+}
+
+/// Wraps an escrow instruction into an auction instruction, the authority PDA not signing it
+fn through_auction(ix: Instruction) -> Instruction {
+    let (authority, _) = auction_authority();
+    let mut accounts = vec![AccountMeta::new_readonly(ix.program_id, false)];
+    accounts.extend(ix.accounts.into_iter().map(|mut meta| {
+        if meta.pubkey == authority {
+            meta.is_signer = false;
+        }
+        meta
+    }));
+    Instruction {
+        program_id: auction_id(),
+        accounts,
+        data: ix.data,
+    }
+}
+
+/// Starts the shared test env with the auction program next to the escrow program
+async fn start() -> Env {
+    Env::start_with(|program_test| {
+        program_test.add_program("auction", auction_id(), processor!(process_auction));
+    })
+    .await
+}
+
+/// Opens an escrow under the auction authority, claiming it is a PDA of `authority_program`
+async fn init_escrow(
+    env: &mut Env,
+    vault: &Pubkey,
+    reference: [u8; 32],
+    authority_program: Pubkey,
+) -> Result<Pubkey, BanksClientError> {
+    let (authority, bump_seed) = auction_authority();
+    let payer = env.payer.insecure_clone();
+    let init_escrow_ix = instruction::init_escrow(
+        &id(),
+        &payer.pubkey(),
+        vault,
+        &authority,
+        &env.context.payer.pubkey(),
+        &env.fee_taker.pubkey(),
+        None,
+        AMOUNT,
+        reference,
+        0,
+        FEE,
+        EscrowMode::Standard,
+        Some(AuthorityProgram {
+            program_id: authority_program,
+            seeds: vec![b"auction".to_vec(), vec![bump_seed]],
+        }),
+    );
+    env.send(&[through_auction(init_escrow_ix)], &[&payer])
+        .await?;
+    Ok(find_escrow_address(&payer.pubkey(), &authority, &reference, &id()).0)
+}
+
+#[tokio::test]
+async fn auction_program_settles_escrow() {
+    let mut env = start().await;
+    let (authority, _) = auction_authority();
+    let vault = env.create_token_account(&env.payer.pubkey(), AMOUNT).await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+
+    let escrow = init_escrow(&mut env, &vault, [1; 32], auction_id())
+        .await
+        .unwrap();
+    let escrow_info = env.escrow(&escrow).await;
+    assert_eq!(escrow_info.authority_pubkey, authority);
+    assert_eq!(escrow_info.authority_program(), Some(&auction_id()));
+
+    let accept_ix = instruction::accept(&id(), &env.payee.pubkey(), &authority, &escrow);
+    let payee = env.payee.insecure_clone();
+    env.send(&[through_auction(accept_ix)], &[&payee])
+        .await
+        .unwrap();
+
+    let rent_payer = env.context.payer.pubkey();
+    let settle_ix = instruction::settle(
+        &id(),
+        &authority,
+        &payee_account,
+        &fee_taker_account,
+        &vault,
+        &escrow,
+        &rent_payer,
+        None,
+        FEE,
+    );
+    env.send(&[through_auction(settle_ix)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT - FEE);
+    assert_eq!(env.token_balance(&fee_taker_account).await, FEE);
+
+    let close_ix = instruction::close(&id(), &authority, &escrow, &rent_payer);
+    env.send(&[through_auction(close_ix)], &[]).await.unwrap();
+}
+
+#[tokio::test]
+async fn authority_program_must_derive_authority() {
+    let mut env = start().await;
+    let vault = env.create_token_account(&env.payer.pubkey(), AMOUNT).await;
+
+    let result = init_escrow(&mut env, &vault, [2; 32], Pubkey::new_unique()).await;
+    assert_escrow_error(result.map(|_| ()), EscrowError::AuthorityProgramMismatch);
+}
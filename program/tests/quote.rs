@@ -0,0 +1,154 @@
+//! Quotes simulated before settling, checked against what the settlement actually pays out.
+
+mod common;
+
+use bpf_program_template::{
+    find_escrow_address, id, instruction,
+    state::{EscrowMode, SettlementQuote},
+};
+use common::{add_native_mint, Env, AMOUNT, FEE};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, rent::Rent, system_instruction};
+use solana_sdk::signature::{Keypair, Signer};
+
+const REFERRER_FEE_BPS: u16 = 2_500;
+
+/// Balance of a recipient, in lamports for the wallets native escrows pay
+async fn balance(env: &mut Env, account: &Pubkey, native: bool) -> u64 {
+    if native {
+        env.account(account)
+            .await
+            .map_or(0, |account| account.lamports)
+    } else {
+        env.token_balance(account).await
+    }
+}
+
+/// Quotes an escrow with a referrer, then settles and closes it, checking every quoted
+/// amount against the balance changes
+async fn quote_matches_settlement(native: bool) {
+    let mut env = Env::start_with(add_native_mint).await;
+    let payer = env.payer.insecure_clone();
+    let authority = env.authority.insecure_clone();
+    let referrer = Keypair::new();
+    // a rent payer of its own, so transaction fees do not blur its balance
+    let rent_payer = Keypair::new();
+    let transfer_ix = system_instruction::transfer(
+        &env.context.payer.pubkey(),
+        &rent_payer.pubkey(),
+        1_000_000_000,
+    );
+    env.send(&[transfer_ix], &[]).await.unwrap();
+
+    let wallets = [
+        env.payee.pubkey(),
+        env.fee_taker.pubkey(),
+        referrer.pubkey(),
+    ];
+    let (vault, recipients) = if native {
+        // native escrows pay the wallets in lamports, which have to stay rent exempt
+        for wallet in &wallets {
+            let transfer_ix = system_instruction::transfer(
+                &env.context.payer.pubkey(),
+                wallet,
+                Rent::default().minimum_balance(0),
+            );
+            env.send(&[transfer_ix], &[]).await.unwrap();
+        }
+        let vault = env.create_native_account(&payer.pubkey(), AMOUNT).await;
+        (vault, wallets)
+    } else {
+        let vault = env.create_token_account(&payer.pubkey(), AMOUNT).await;
+        let mut recipients = [Pubkey::default(); 3];
+        for (recipient, wallet) in recipients.iter_mut().zip(&wallets) {
+            *recipient = env.create_token_account(wallet, 0).await;
+        }
+        (vault, recipients)
+    };
+    let [payee_account, fee_taker_account, referrer_account] = recipients;
+
+    let reference = Keypair::new().pubkey().to_bytes();
+    let init_escrow_ix = instruction::init_escrow(
+        &id(),
+        &payer.pubkey(),
+        &vault,
+        &authority.pubkey(),
+        &rent_payer.pubkey(),
+        &env.fee_taker.pubkey(),
+        Some(&referrer.pubkey()),
+        AMOUNT,
+        reference,
+        REFERRER_FEE_BPS,
+        FEE,
+        EscrowMode::Standard,
+        None,
+    );
+    env.send(&[init_escrow_ix], &[&payer, &authority, &rent_payer])
+        .await
+        .unwrap();
+    let (escrow, _) = find_escrow_address(&payer.pubkey(), &authority.pubkey(), &reference, &id());
+    env.accept(&escrow).await.unwrap();
+
+    let quote_ix = instruction::quote(&id(), &escrow, &vault, FEE);
+    let transaction = env.transaction(&[quote_ix], &[]).await;
+    let simulation = env
+        .context
+        .banks_client
+        .simulate_transaction(transaction)
+        .await
+        .unwrap();
+    assert!(matches!(simulation.result, Some(Ok(()))));
+    let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+    assert_eq!(return_data.program_id, id());
+    let quote = SettlementQuote::unpack_from_slice(&return_data.data).unwrap();
+    assert_eq!(quote.referrer_fee, FEE / 4);
+    assert_eq!(quote.fee, FEE - FEE / 4);
+    assert_eq!(quote.payee_amount + quote.fee + quote.referrer_fee, AMOUNT);
+
+    let mut before = [0; 3];
+    for (balance_before, recipient) in before.iter_mut().zip(&recipients) {
+        *balance_before = balance(&mut env, recipient, native).await;
+    }
+    let rent_payer_before = env.account(&rent_payer.pubkey()).await.unwrap().lamports;
+
+    let settle_ix = instruction::settle(
+        &id(),
+        &authority.pubkey(),
+        &payee_account,
+        &fee_taker_account,
+        &vault,
+        &escrow,
+        &rent_payer.pubkey(),
+        Some(&referrer_account),
+        FEE,
+    );
+    let close_ix = instruction::close(&id(), &authority.pubkey(), &escrow, &rent_payer.pubkey());
+    env.send(&[settle_ix, close_ix], &[&authority])
+        .await
+        .unwrap();
+
+    let quoted = [quote.payee_amount, quote.fee, quote.referrer_fee];
+    for ((recipient, balance_before), quoted) in recipients.iter().zip(before).zip(quoted) {
+        assert_eq!(
+            balance(&mut env, recipient, native).await - balance_before,
+            quoted
+        );
+    }
+    assert_eq!(
+        env.account(&rent_payer.pubkey()).await.unwrap().lamports - rent_payer_before,
+        quote.vault_rent + quote.escrow_rent
+    );
+    assert_eq!(
+        quote.vault_rent,
+        Rent::default().minimum_balance(spl_token::state::Account::LEN)
+    );
+}
+
+#[tokio::test]
+async fn quote_matches_spl_settlement() {
+    quote_matches_settlement(false).await;
+}
+
+#[tokio::test]
+async fn quote_matches_native_settlement() {
+    quote_matches_settlement(true).await;
+}
@@ -0,0 +1,94 @@
+//! Recurring escrows released period by period through `Crank`.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError,
+    id, instruction,
+    state::{EscrowMode, EscrowStatus},
+};
+use common::{assert_escrow_error, Env, AMOUNT};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::BanksClientError;
+use solana_sdk::signature::Signer;
+
+const PERIOD: i64 = 10;
+const AMOUNT_PER_PERIOD: u64 = 400_000;
+
+async fn crank(
+    env: &mut Env,
+    escrow: &Pubkey,
+    vault: &Pubkey,
+    payee_account: &Pubkey,
+) -> Result<(), BanksClientError> {
+    let authority = env.authority.insecure_clone();
+    let crank_ix = instruction::crank(
+        &id(),
+        &authority.pubkey(),
+        payee_account,
+        vault,
+        &env.context.payer.pubkey(),
+        escrow,
+    );
+    env.send(&[crank_ix], &[&authority]).await
+}
+
+fn released_periods(mode: EscrowMode) -> u64 {
+    match mode {
+        EscrowMode::Recurring {
+            released_periods, ..
+        } => released_periods,
+        mode => panic!("not a recurring escrow: {:?}", mode),
+    }
+}
+
+#[tokio::test]
+async fn crank_releases_due_periods() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let (escrow, vault) = env
+        .init_escrow(EscrowMode::Recurring {
+            period: PERIOD,
+            amount_per_period: AMOUNT_PER_PERIOD,
+            released_periods: 0,
+        })
+        .await
+        .unwrap();
+    env.accept(&escrow).await.unwrap();
+    let accepted_at = env.escrow(&escrow).await.accepted_at;
+
+    // the first period only ends one period after acceptance
+    env.set_time(accepted_at + PERIOD - 1).await;
+    let result = crank(&mut env, &escrow, &vault, &payee_account).await;
+    assert_escrow_error(result, EscrowError::ReleaseNotDue);
+
+    env.set_time(accepted_at + PERIOD).await;
+    crank(&mut env, &escrow, &vault, &payee_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT_PER_PERIOD);
+    assert_eq!(env.token_balance(&vault).await, AMOUNT - AMOUNT_PER_PERIOD);
+    let escrow_info = env.escrow(&escrow).await;
+    assert_eq!(released_periods(escrow_info.mode), 1);
+    assert_eq!(escrow_info.status, EscrowStatus::Active);
+
+    // a single release per period
+    let result = crank(&mut env, &escrow, &vault, &payee_account).await;
+    assert_escrow_error(result, EscrowError::ReleaseNotDue);
+
+    env.set_time(accepted_at + 2 * PERIOD).await;
+    crank(&mut env, &escrow, &vault, &payee_account)
+        .await
+        .unwrap();
+
+    // the last period gets what is left and closes the vault
+    env.set_time(accepted_at + 3 * PERIOD).await;
+    crank(&mut env, &escrow, &vault, &payee_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT);
+    assert!(env.account(&vault).await.is_none());
+    let escrow_info = env.escrow(&escrow).await;
+    assert_eq!(released_periods(escrow_info.mode), 3);
+    assert_eq!(escrow_info.status, EscrowStatus::Settled);
+}
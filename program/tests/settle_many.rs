@@ -0,0 +1,93 @@
+//! Batch settlements of escrows with and without a referrer.
+
+mod common;
+
+use bpf_program_template::{error::EscrowError, id, instruction, state::EscrowMode};
+use common::{assert_escrow_error, Env, AMOUNT, FEE};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Settles the escrows at 10 basis points, the fee of an `AMOUNT` escrow being `FEE`
+async fn settle_many(
+    env: &mut Env,
+    fee_taker_account: &Pubkey,
+    settlements: &[(Pubkey, Pubkey, Pubkey, Option<Pubkey>)],
+) -> Result<(), solana_program_test::BanksClientError> {
+    let authority = env.authority.insecure_clone();
+    let settle_many_ix = instruction::settle_many(
+        &id(),
+        &authority.pubkey(),
+        fee_taker_account,
+        &env.context.payer.pubkey(),
+        settlements,
+        10,
+    );
+    env.send(&[settle_many_ix], &[&authority]).await
+}
+
+#[tokio::test]
+async fn settles_referred_escrows_with_others() {
+    let mut env = Env::start().await;
+    let referrer = Keypair::new();
+    let referrer_account = env.create_token_account(&referrer.pubkey(), 0).await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+
+    let (referred, referred_vault) = env
+        .init_escrow_with(EscrowMode::Standard, Some(&referrer.pubkey()), 5_000)
+        .await
+        .unwrap();
+    env.accept(&referred).await.unwrap();
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+    env.accept(&escrow).await.unwrap();
+
+    settle_many(
+        &mut env,
+        &fee_taker_account,
+        &[
+            (
+                referred,
+                referred_vault,
+                payee_account,
+                Some(referrer_account),
+            ),
+            (escrow, vault, payee_account, None),
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, 2 * (AMOUNT - FEE));
+    assert_eq!(env.token_balance(&referrer_account).await, FEE / 2);
+    assert_eq!(env.token_balance(&fee_taker_account).await, FEE + FEE / 2);
+}
+
+#[tokio::test]
+async fn referred_escrow_needs_its_referrer() {
+    let mut env = Env::start().await;
+    let referrer = Keypair::new();
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+    let (referred, referred_vault) = env
+        .init_escrow_with(EscrowMode::Standard, Some(&referrer.pubkey()), 5_000)
+        .await
+        .unwrap();
+    env.accept(&referred).await.unwrap();
+
+    let result = settle_many(
+        &mut env,
+        &fee_taker_account,
+        &[(referred, referred_vault, payee_account, None)],
+    )
+    .await;
+    assert_escrow_error(result, EscrowError::ReferrerMismatch);
+
+    // some other account passed as the referrer's
+    let result = settle_many(
+        &mut env,
+        &fee_taker_account,
+        &[(referred, referred_vault, payee_account, Some(payee_account))],
+    )
+    .await;
+    assert_escrow_error(result, EscrowError::ReferrerMismatch);
+    assert_eq!(env.token_balance(&referred_vault).await, AMOUNT);
+}
@@ -0,0 +1,91 @@
+//! Rotating the authority of an escrow with `UpdateAuthority`.
+
+mod common;
+
+use bpf_program_template::{error::EscrowError, id, instruction, state::EscrowMode};
+use common::{assert_escrow_error, assert_instruction_error, Env, AMOUNT, FEE};
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+
+#[tokio::test]
+async fn old_authority_rejected_after_rotation() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let fee_taker_account = env.create_token_account(&env.fee_taker.pubkey(), 0).await;
+    let (escrow, vault) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+    env.accept(&escrow).await.unwrap();
+
+    let old_authority = env.authority.insecure_clone();
+    let new_authority = Keypair::new();
+    let update_ix = instruction::update_authority(
+        &id(),
+        &old_authority.pubkey(),
+        &escrow,
+        &new_authority.pubkey(),
+        true,
+    );
+    env.send(&[update_ix], &[&old_authority, &new_authority])
+        .await
+        .unwrap();
+    assert_eq!(
+        env.escrow(&escrow).await.authority_pubkey,
+        new_authority.pubkey()
+    );
+
+    let result = env
+        .settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await;
+    assert_escrow_error(result, EscrowError::InvalidAuthorityId);
+    // nor can it rotate the authority back
+    let update_ix = instruction::update_authority(
+        &id(),
+        &old_authority.pubkey(),
+        &escrow,
+        &old_authority.pubkey(),
+        false,
+    );
+    let result = env.send(&[update_ix], &[&old_authority]).await;
+    assert_escrow_error(result, EscrowError::InvalidAuthorityId);
+
+    env.authority = new_authority;
+    env.settle(&escrow, &vault, &payee_account, &fee_taker_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT - FEE);
+}
+
+#[tokio::test]
+async fn new_authority_account_checked() {
+    let mut env = Env::start().await;
+    let (escrow, _) = env.init_escrow(EscrowMode::Standard).await.unwrap();
+    let authority = env.authority.insecure_clone();
+    let new_authority = Keypair::new();
+
+    let mut update_ix = instruction::update_authority(
+        &id(),
+        &authority.pubkey(),
+        &escrow,
+        &new_authority.pubkey(),
+        true,
+    );
+    update_ix.accounts[2].is_signer = false;
+    let result = env.send(&[update_ix], &[&authority]).await;
+    assert_instruction_error(result, InstructionError::MissingRequiredSignature);
+
+    let other = Keypair::new();
+    let mut update_ix = instruction::update_authority(
+        &id(),
+        &authority.pubkey(),
+        &escrow,
+        &new_authority.pubkey(),
+        true,
+    );
+    update_ix.accounts[2].pubkey = other.pubkey();
+    let result = env.send(&[update_ix], &[&authority, &other]).await;
+    assert_escrow_error(result, EscrowError::NewAuthorityMismatch);
+
+    assert_eq!(
+        env.escrow(&escrow).await.authority_pubkey,
+        authority.pubkey()
+    );
+}
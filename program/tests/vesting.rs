@@ -0,0 +1,128 @@
+//! Vesting escrows withdrawn by the payee as they vest and revoked by the authority.
+
+mod common;
+
+use bpf_program_template::{
+    error::EscrowError,
+    id, instruction,
+    state::{EscrowMode, EscrowStatus},
+};
+use common::{assert_escrow_error, Env, AMOUNT};
+use solana_program::{clock::UnixTimestamp, pubkey::Pubkey};
+use solana_program_test::BanksClientError;
+use solana_sdk::signature::Signer;
+
+/// Opens and accepts an escrow vesting over 100 seconds from now with a cliff after 25,
+/// returning it, its vault and the start of the vesting
+async fn init_vesting(env: &mut Env) -> (Pubkey, Pubkey, UnixTimestamp) {
+    let start_at = env.now().await;
+    let (escrow, vault) = env
+        .init_escrow(EscrowMode::Vesting {
+            start_at,
+            cliff_at: start_at + 25,
+            end_at: start_at + 100,
+            released: 0,
+        })
+        .await
+        .unwrap();
+    env.accept(&escrow).await.unwrap();
+    (escrow, vault, start_at)
+}
+
+async fn withdraw(
+    env: &mut Env,
+    escrow: &Pubkey,
+    vault: &Pubkey,
+    payee_account: &Pubkey,
+) -> Result<(), BanksClientError> {
+    let payee = env.payee.insecure_clone();
+    let withdraw_ix = instruction::withdraw(
+        &id(),
+        &payee.pubkey(),
+        payee_account,
+        vault,
+        &env.context.payer.pubkey(),
+        escrow,
+    );
+    env.send(&[withdraw_ix], &[&payee]).await
+}
+
+#[tokio::test]
+async fn withdraw_follows_schedule() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let (escrow, vault, start_at) = init_vesting(&mut env).await;
+
+    env.set_time(start_at + 24).await;
+    let result = withdraw(&mut env, &escrow, &vault, &payee_account).await;
+    assert_escrow_error(result, EscrowError::ReleaseNotDue);
+
+    // linear from the start once past the cliff
+    env.set_time(start_at + 50).await;
+    withdraw(&mut env, &escrow, &vault, &payee_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT / 2);
+    let result = withdraw(&mut env, &escrow, &vault, &payee_account).await;
+    assert_escrow_error(result, EscrowError::ReleaseNotDue);
+
+    env.set_time(start_at + 100).await;
+    withdraw(&mut env, &escrow, &vault, &payee_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, AMOUNT);
+    assert!(env.account(&vault).await.is_none());
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Settled);
+}
+
+#[tokio::test]
+async fn revoke_returns_unvested_part() {
+    let mut env = Env::start().await;
+    let payee_account = env.create_token_account(&env.payee.pubkey(), 0).await;
+    let payer_account = env.create_token_account(&env.payer.pubkey(), 0).await;
+    let (escrow, vault, start_at) = init_vesting(&mut env).await;
+
+    env.set_time(start_at + 30).await;
+    withdraw(&mut env, &escrow, &vault, &payee_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, 300_000);
+
+    env.set_time(start_at + 40).await;
+    let authority = env.authority.insecure_clone();
+    let revoke_ix = instruction::revoke(
+        &id(),
+        &authority.pubkey(),
+        &payer_account,
+        &vault,
+        &env.context.payer.pubkey(),
+        &escrow,
+    );
+    env.send(&[revoke_ix], &[&authority]).await.unwrap();
+
+    // 400_000 vested, of which 100_000 are left for the payee
+    assert_eq!(env.token_balance(&payer_account).await, 600_000);
+    assert_eq!(env.token_balance(&vault).await, 100_000);
+    let escrow_info = env.escrow(&escrow).await;
+    assert_eq!(escrow_info.amount, 400_000);
+    assert_eq!(escrow_info.status, EscrowStatus::Active);
+    assert_eq!(
+        escrow_info.mode,
+        EscrowMode::Vesting {
+            start_at,
+            cliff_at: start_at + 25,
+            end_at: start_at + 40,
+            released: 300_000,
+        }
+    );
+
+    // nothing vests after the revocation, what had vested is still withdrawn
+    env.set_time(start_at + 90).await;
+    withdraw(&mut env, &escrow, &vault, &payee_account)
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&payee_account).await, 400_000);
+    assert_eq!(env.token_balance(&payer_account).await, 600_000);
+    assert!(env.account(&vault).await.is_none());
+    assert_eq!(env.escrow(&escrow).await.status, EscrowStatus::Settled);
+}